@@ -1,50 +1,256 @@
 extern crate rayon;
 
-use rayon::prelude::*;
+use {
+    rayon::prelude::*,
+    std::{
+	ascii,
+	collections::BTreeSet,
+	env,
+	io::{self, BufRead, BufReader, Read, Write},
+    },
+};
 
-fn part1(input: &str) -> usize {
-    let mut chemicals = String::from(input);
+// Table of which unit pairs annihilate when they end up next to each other.
+// Pairs are ordered, so `ab` may react while `ba` doesn't.
+#[derive(Clone)]
+struct Reactions {
+    table: Vec<bool>,
+}
+
+impl Reactions {
+    // No pair of units reacts.
+    fn new() -> Reactions {
+	Reactions { table: vec![false; 256 * 256] }
+    }
+
+    // The puzzle's rule: units react if they are the same type but of
+    // opposite polarity.
+    fn polarity() -> Reactions {
+	let mut reactions = Reactions::new();
+	for (lower, upper) in (b'a'..=b'z').zip(b'A'..=b'Z') {
+	    reactions.insert(lower, upper);
+	    reactions.insert(upper, lower);
+	}
+	reactions
+    }
+
+    // Makes `left` react with `right` when `left` comes first.
+    fn insert(&mut self, left: u8, right: u8) {
+	self.table[Reactions::index(left, right)] = true;
+    }
+
+    fn reacts(&self, left: u8, right: u8) -> bool {
+	self.table[Reactions::index(left, right)]
+    }
+
+    // Whether the order in which reactions happen can't change the result.
+    // Two reactions only compete for a unit that reacts on both sides, so that
+    // holds unless some unit has different partners on its left and its
+    // right: with `ab` and `bc` reacting, `abc` reduces to either `c` or `a`,
+    // while `ab` and `cb` never compete.
+    fn is_confluent(&self) -> bool {
+	(0..=255).all(|unit| {
+	    let lefts = (0..=255).filter(|&left| self.reacts(left, unit)).collect::<BTreeSet<u8>>();
+	    let rights = (0..=255).filter(|&right| self.reacts(unit, right)).collect::<BTreeSet<u8>>();
+	    lefts.is_empty() || rights.is_empty() || lefts.union(&rights).count() <= 1
+	})
+    }
+
+    fn index(left: u8, right: u8) -> usize {
+	(left as usize) << 8 | right as usize
+    }
+}
+
+// Feeds more units into a partially reduced polymer. Each unit either
+// annihilates the unit on top of the stack or gets pushed, so whatever is left
+// on the stack is the fully reacted polymer.
+fn react_into<I>(reactions: &Reactions, stack: &mut Vec<u8>, units: I)
+where
+    I: IntoIterator<Item = u8>,
+{
+    for unit in units {
+	match stack.last() {
+	    Some(&top) if reactions.reacts(top, unit) => {
+		stack.pop();
+	    }
+	    _ => stack.push(unit),
+	}
+    }
+}
+
+// Reduces the polymer in a single pass.
+fn reduce<I: IntoIterator<Item = u8>>(reactions: &Reactions, units: I) -> Vec<u8> {
+    let mut stack = Vec::new();
+    react_into(reactions, &mut stack, units);
+    stack
+}
+
+// Number of units in each chunk reduced on its own by `reduce_parallel`.
+const CHUNK_LEN: usize = 1 << 16;
+
+// Reduces chunks of the polymer in parallel and then merges neighbouring
+// chunks, letting units react across the boundary. That only matches the
+// sequential result when the reactions are confluent, so other tables fall
+// back to `reduce`.
+fn reduce_parallel(reactions: &Reactions, polymer: &[u8], chunk_len: usize) -> Vec<u8> {
+    if !reactions.is_confluent() {
+	return reduce(reactions, polymer.iter().cloned());
+    }
+    polymer.par_chunks(chunk_len)
+	   .map(|chunk| reduce(reactions, chunk.iter().cloned()))
+	   .reduce(Vec::new, |mut left, right| {
+	       react_into(reactions, &mut left, right);
+	       left
+	   })
+}
+
+// Reduces a polymer read incrementally from `input` and writes the result to
+// `out`, returning its length. Only the surviving units are ever held in
+// memory. Whitespace isn't part of the polymer, so it is skipped.
+fn reduce_stream<R, W>(reactions: &Reactions, input: R, mut out: W) -> io::Result<usize>
+where
+    R: Read,
+    W: Write,
+{
+    let mut reader = BufReader::new(input);
+    let mut stack = Vec::new();
     loop {
-	let mut iter = chemicals.chars().peekable();
-	let mut accum = String::with_capacity(chemicals.len());
-	let mut letter = iter.next();
-	let mut changed = false;
-	while let Some(l) = letter {
-	    if let Some(p) = iter.peek() {
-		if l != *p && l.to_ascii_lowercase() == p.to_ascii_lowercase() {
-		    changed = true;
-		    iter.next();
-		    letter = iter.next();
+	let len = {
+	    let buf = reader.fill_buf()?;
+	    react_into(reactions, &mut stack, buf.iter()
+						 .cloned()
+						 .filter(|c| !c.is_ascii_whitespace()));
+	    buf.len()
+	};
+	if len == 0 {
+	    break;
+	}
+	reader.consume(len);
+    }
+    out.write_all(&stack)?;
+    out.flush()?;
+    Ok(stack.len())
+}
+
+// Longest polymer for which `main` renders the intermediate states in a trace.
+const MAX_TRACED_STATE_LEN: usize = 80;
+
+// Reduces the polymer the same way as `reduce`, but writes the first `limit`
+// reactions to `out`: the input position and unit of both sides and the depth
+// of the stack once the pair is gone. With `show_states` set, each reaction is
+// followed by the polymer left after it, as in the puzzle text.
+fn trace<W: Write>(reactions: &Reactions,
+		   input: &str,
+		   limit: usize,
+		   show_states: bool,
+		   out: &mut W) -> io::Result<Vec<u8>> {
+    let input = input.as_bytes();
+    if show_states {
+	writeln!(out, "{}", String::from_utf8_lossy(input))?;
+    }
+    // Holds the input position of each unit left on the stack.
+    let mut stack: Vec<usize> = Vec::new();
+    let mut count = 0;
+    for (position, &unit) in input.iter().enumerate() {
+	match stack.last() {
+	    Some(&top) if reactions.reacts(input[top], unit) => {
+		stack.pop();
+		count += 1;
+		if count > limit {
 		    continue;
 		}
+		writeln!(out, "#{}: {}@{} {}@{} depth {}",
+			 count, input[top] as char, top,
+			 unit as char, position, stack.len())?;
+		if show_states {
+		    let state = stack.iter()
+				     .map(|&i| input[i])
+				     .chain(input[position + 1..].iter().cloned())
+				     .collect::<Vec<_>>();
+		    writeln!(out, "{}", String::from_utf8_lossy(&state))?;
+		}
 	    }
-	    letter = iter.next();
-	    accum.push(l);
-	}
-	if !changed {
-	    return chemicals.len();
+	    _ => stack.push(position),
 	}
-	chemicals = accum;
     }
+    if count > limit {
+	writeln!(out, "... {} more reactions", count - limit)?;
+    }
+    Ok(stack.into_iter().map(|i| input[i]).collect())
 }
 
-fn part2(input: &str) -> usize {
-    let letters: Vec<_> = (b'a'..b'z').collect();
-    letters.par_iter().map(|c| *c as char).map(|lowercase| {
-	let uppercase = lowercase.to_ascii_uppercase();
+fn part1(input: &str) -> usize {
+    reduce_parallel(&Reactions::polarity(), input.as_bytes(), CHUNK_LEN).len()
+}
 
-	let input = input.chars()
-	                 .filter(|c| *c != lowercase)
-			 .filter(|c| *c != uppercase)
-			 .collect::<String>();
-	part1(input.as_str())
-    }).min().unwrap()
+// Returns the reduced length of the polymer with each of its unit types
+// removed, shortest first. `unit_type` maps a unit to its type, which is what
+// gets reported. Types are raw bytes, so input that isn't ASCII is reported
+// byte by byte.
+fn removal_report(reactions: &Reactions, input: &str, unit_type: fn(&u8) -> u8) -> Vec<(u8, usize)> {
+    // Types that react away entirely are still part of the polymer, so they
+    // have to be taken from the input rather than from the reduced polymer.
+    let unit_types = input.bytes()
+			  .map(|c| unit_type(&c))
+			  .collect::<BTreeSet<u8>>()
+			  .into_iter()
+			  .collect::<Vec<_>>();
+    // Reactions within a type vanish together with the type, so with confluent
+    // reactions that never cross types, removing a type can't undo a reaction
+    // and it's enough to start from the already reduced polymer. Otherwise a
+    // reaction may have taken away the partner of a removed unit, or kept a
+    // removed unit from reacting with its other neighbour, so start over from
+    // the input.
+    let within_types = (0..=255).all(|left| {
+	(0..=255).all(|right| !reactions.reacts(left, right) || unit_type(&left) == unit_type(&right))
+    });
+    let polymer = if within_types && reactions.is_confluent() {
+	reduce_parallel(reactions, input.as_bytes(), CHUNK_LEN)
+    } else {
+	input.as_bytes().to_vec()
+    };
+    let mut report = unit_types.par_iter().map(|&removed| {
+	let len = reduce(reactions, polymer.iter()
+					   .cloned()
+					   .filter(|c| unit_type(c) != removed)).len();
+	(removed, len)
+    }).collect::<Vec<_>>();
+    report.sort_by_key(|&(unit_type, len)| (len, unit_type));
+    report
+}
+
+fn part2(input: &str) -> usize {
+    // Units of either case make up one type.
+    removal_report(&Reactions::polarity(), input, u8::to_ascii_lowercase)
+	.first()
+	.map_or(0, |&(_, len)| len)
 }
 
 fn main() {
     let input = include_str!("input.txt").trim();
+    let mut args = env::args().skip(1);
+    match args.next().as_deref() {
+	Some("--trace") => {
+	    let limit = args.next().map_or(Ok(usize::MAX), |n| n.parse()).unwrap();
+	    let stdout = io::stdout();
+	    let show_states = input.len() <= MAX_TRACED_STATE_LEN;
+	    trace(&Reactions::polarity(), input, limit, show_states, &mut stdout.lock()).unwrap();
+	    return;
+	}
+	// Reduces a polymer of any size from stdin to stdout.
+	Some("--stream") => {
+	    let stdin = io::stdin();
+	    let stdout = io::stdout();
+	    reduce_stream(&Reactions::polarity(), stdin.lock(), stdout.lock()).unwrap();
+	    return;
+	}
+	_ => {}
+    }
     println!("part1: {}", part1(input));
     println!("part2: {}", part2(input));
+    for (unit_type, len) in removal_report(&Reactions::polarity(), input, u8::to_ascii_lowercase) {
+	println!("  without {}: {}", ascii::escape_default(unit_type), len);
+    }
 }
 #[cfg(test)]
 mod tests {
@@ -60,4 +266,115 @@ mod tests {
 	assert_eq!(part1("BAcab"), 5);
 	assert_eq!(part1("AbBca"), 3);
     }
+
+    #[test]
+    fn reduce_test() {
+	let polarity = Reactions::polarity();
+	assert_eq!(reduce(&polarity, "dabAcCaCBAcCcaDA".bytes()), b"dabCBAcaDA");
+	let nested = "a".repeat(1000) + &"A".repeat(1000);
+	assert_eq!(reduce(&polarity, nested.bytes()), b"");
+	assert_eq!(reduce(&polarity, "1+-!".bytes()), b"1+-!");
+    }
+
+    #[test]
+    fn custom_reactions_test() {
+	let mut reactions = Reactions::new();
+	reactions.insert(b'a', b'b');
+	reactions.insert(b'+', b'-');
+	assert_eq!(reduce(&reactions, "ab".bytes()), b"");
+	assert_eq!(reduce(&reactions, "ba".bytes()), b"ba");
+	assert_eq!(reduce(&reactions, "a+-bbaAB".bytes()), b"baAB");
+	assert_eq!(reduce(&reactions, "-+".bytes()), b"-+");
+    }
+
+    // Deterministic xorshift so the random polymers are the same every run.
+    fn random_polymer(seed: &mut u64, len: usize, units: &[u8]) -> Vec<u8> {
+	(0..len).map(|_| {
+	    *seed ^= *seed << 13;
+	    *seed ^= *seed >> 7;
+	    *seed ^= *seed << 17;
+	    units[(*seed % units.len() as u64) as usize]
+	}).collect()
+    }
+
+    #[test]
+    fn reduce_parallel_test() {
+	let polarity = Reactions::polarity();
+	let mut skewed = Reactions::new();
+	skewed.insert(b'a', b'b');
+	skewed.insert(b'b', b'c');
+	assert!(polarity.is_confluent());
+	assert!(!skewed.is_confluent());
+	let mut shared = Reactions::new();
+	shared.insert(b'a', b'b');
+	shared.insert(b'c', b'b');
+	assert!(shared.is_confluent());
+
+	let mut seed = 0x2018_0005;
+	for &(ref reactions, units) in &[(polarity, &b"aAbBcC"[..]),
+					 (skewed, &b"abc"[..]),
+					 (shared, &b"abc"[..])] {
+	    for &len in &[0, 1, 7, 100, 1000] {
+		let polymer = random_polymer(&mut seed, len, units);
+		let expected = reduce(reactions, polymer.iter().cloned());
+		for &chunk_len in &[1, 2, 3, 16, CHUNK_LEN] {
+		    assert_eq!(reduce_parallel(reactions, &polymer, chunk_len), expected);
+		}
+	    }
+	}
+    }
+
+    #[test]
+    fn reduce_stream_test() {
+	let input = "dabAcC".as_bytes().chain("aCBAcCcaDA\n".as_bytes());
+	let mut out = Vec::new();
+	assert_eq!(reduce_stream(&Reactions::polarity(), input, &mut out).unwrap(), 10);
+	assert_eq!(out, b"dabCBAcaDA");
+    }
+
+    #[test]
+    fn trace_test() {
+	let mut out = Vec::new();
+	let polymer = trace(&Reactions::polarity(), "dabAcCaCBAcCcaDA", 2, true, &mut out).unwrap();
+	assert_eq!(polymer, b"dabCBAcaDA");
+	assert_eq!(String::from_utf8(out).unwrap(), "dabAcCaCBAcCcaDA
+#1: c@4 C@5 depth 4
+dabAaCBAcCcaDA
+#2: A@3 a@6 depth 3
+dabCBAcCcaDA
+... 1 more reactions
+");
+    }
+
+    #[test]
+    fn part2_test() {
+	assert_eq!(part2("dabAcCaCBAcCcaDA"), 4);
+	assert_eq!(part2("zZ"), 0);
+	assert_eq!(part2(""), 0);
+    }
+
+    #[test]
+    fn removal_report_test() {
+	let polarity = Reactions::polarity();
+	assert_eq!(removal_report(&polarity, "dabAcCaCBAcCcaDA", u8::to_ascii_lowercase),
+		   vec![(b'c', 4), (b'a', 6), (b'd', 6), (b'b', 8)]);
+	assert_eq!(removal_report(&polarity, "zaZ", u8::to_ascii_lowercase), vec![(b'a', 0), (b'z', 1)]);
+	assert_eq!(removal_report(&polarity, "aAb", u8::to_ascii_lowercase), vec![(b'b', 0), (b'a', 1)]);
+	assert_eq!(removal_report(&polarity, "zZ", u8::to_ascii_lowercase), vec![(b'z', 0)]);
+	assert_eq!(removal_report(&polarity, "é", u8::to_ascii_lowercase), vec![(0xa9, 1), (0xc3, 1)]);
+
+	// Removing `a` leaves `bc`, which still reacts even though `b` already
+	// went with `a` in the reduced polymer.
+	let mut skewed = Reactions::new();
+	skewed.insert(b'a', b'b');
+	skewed.insert(b'b', b'c');
+	assert_eq!(removal_report(&skewed, "abc", u8::to_ascii_lowercase),
+		   vec![(b'a', 0), (b'c', 0), (b'b', 2)]);
+
+	// Removing `a` leaves its partner `b` behind when each unit is its own
+	// type.
+	let mut pair = Reactions::new();
+	pair.insert(b'a', b'b');
+	assert_eq!(removal_report(&pair, "ab", |c| *c), vec![(b'a', 1), (b'b', 1)]);
+    }
 }
@@ -6,21 +6,39 @@ use {
     failure::Error,
     rayon::prelude::*,
     std::{
-        cmp::Ordering,
-        collections::{HashMap, HashSet},
+	collections::{BTreeSet, HashMap, HashSet, VecDeque},
+	env,
+	fs::File,
+	io::{self, BufWriter, Write},
 	str::FromStr,
     },
 };
 
+// A coordinate from the input, in 2D or, if it has a `z`, in 3D.
 #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 struct Point {
     x : i32,
     y : i32,
+    z : Option<i32>,
 }
 
 impl Point {
-    fn dist(&self, other: &Point) -> i32 {
-	i32::abs(self.x - other.x) + i32::abs(self.y - other.y)
+    fn dims(&self) -> usize {
+	if self.z.is_some() { 3 } else { 2 }
+    }
+
+    // Coordinates along each axis, with 2D points lying at `z` 0.
+    fn coords(&self) -> [i64; 3] {
+	[i64::from(self.x), i64::from(self.y), i64::from(self.z.unwrap_or(0))]
+    }
+
+    // Point at `coords` with as many dimensions as this one.
+    fn with_coords(&self, coords: [i64; 3]) -> Point {
+	Point {
+	    x: coords[0] as i32,
+	    y: coords[1] as i32,
+	    z: self.z.map(|_| coords[2] as i32),
+	}
     }
 }
 
@@ -29,113 +47,857 @@ impl FromStr for Point {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
 	let s = s.split(", ")
-	         .map(|num| str::parse::<i32>(num))
+		 .map(str::parse::<i32>)
 		 .collect::<Result<Vec<_>, std::num::ParseIntError>>()?;
-	if s.len() != 2 {
+	if s.len() != 2 && s.len() != 3 {
 	    return Err(format_err!("invalid string: {:?}", s));
 	}
 	Ok(Point {
 	    x: s[0],
 	    y: s[1],
+	    z: s.get(2).cloned(),
 	})
     }
 }
 
 fn parse_input(input: &str) -> Vec<Point> {
-    input.lines()
-	 .filter(|line| !line.is_empty())
-	 .map(|line| str::parse::<Point>(line))
-	 .collect::<Result<Vec<_>, Error>>()
-	 .unwrap()
+    let points = input.lines()
+		      .filter(|line| !line.is_empty())
+		      .map(str::parse::<Point>)
+		      .collect::<Result<Vec<_>, Error>>()
+		      .unwrap();
+    if points.iter().any(|p| p.dims() != points[0].dims()) {
+	panic!("mixed 2D and 3D coordinates");
+    }
+    points
+}
+
+// Number of dimensions the points live in.
+fn dims(points: &[Point]) -> usize {
+    points[0].dims()
+}
+
+// Smallest and largest coordinate of the points along each axis.
+fn bounding_box(points: &[Point]) -> ([i64; 3], [i64; 3]) {
+    let mut min = points[0].coords();
+    let mut max = min;
+    for point in points {
+	let coords = point.coords();
+	for axis in 0..3 {
+	    min[axis] = min[axis].min(coords[axis]);
+	    max[axis] = max[axis].max(coords[axis]);
+	}
+    }
+    (min, max)
+}
+
+fn sub(a: [i64; 3], b: [i64; 3]) -> [i64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [i64; 3], b: [i64; 3]) -> i64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [i64; 3], b: [i64; 3]) -> [i64; 3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Metric {
+    Manhattan,
+    Chebyshev,
+    SquaredEuclidean,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd)]
-struct PointAndDist(i32, Point);
+impl Metric {
+    fn dist(self, a: &Point, b: &Point) -> i64 {
+	self.len(sub(a.coords(), b.coords()))
+    }
+
+    // Length of `offset`.
+    fn len(self, offset: [i64; 3]) -> i64 {
+	let abs = offset.iter().map(|d| d.abs());
+	match self {
+	    Metric::Manhattan => abs.sum(),
+	    Metric::Chebyshev => abs.max().unwrap(),
+	    Metric::SquaredEuclidean => abs.map(|d| d * d).sum(),
+	}
+    }
+
+    // Whether the length of an offset is the sum of the lengths along each
+    // axis.
+    fn is_separable(self) -> bool {
+	self != Metric::Chebyshev
+    }
 
-impl Ord for PointAndDist {
-    fn cmp(&self, other: &PointAndDist) -> Ordering {
-        self.0.cmp(&other.0)
+    // Moves to the neighbouring cells one unit of distance away. Flooding
+    // outwards along these finds the nearest points of every cell, which only
+    // works for metrics that grow by one per step.
+    fn steps(self, dims: usize) -> Option<Vec<[i64; 3]>> {
+	if self == Metric::SquaredEuclidean {
+	    return None;
+	}
+	let dzs = if dims == 3 { -1..=1 } else { 0..=0 };
+	let mut steps = Vec::new();
+	for dz in dzs {
+	    for dy in -1..=1 {
+		for dx in -1..=1 {
+		    let moved = [dx, dy, dz].iter().filter(|&&d| d != 0).count();
+		    if moved == 1 || (moved > 1 && self == Metric::Chebyshev) {
+			steps.push([dx, dy, dz]);
+		    }
+		}
+	    }
+	}
+	Some(steps)
+    }
+
+    // Returns the corners of the area that has to be mapped so that it holds
+    // every finite region in full, and, for the metrics whose infinite regions
+    // are found on the border of the map, at least one cell of each of those.
+    fn extent(self, points: &[Point]) -> (Point, Point) {
+	let (mut min, mut max) = bounding_box(points);
+	let margin = match self {
+	    // Past the bounding box, stepping further away changes the
+	    // distance to every point by the same amount.
+	    Metric::Manhattan => 1,
+	    // Finite regions may stick out of the bounding box, so it has to
+	    // be grown to fit them.
+	    Metric::Chebyshev => {
+		let (low, high) = chebyshev_bounds(points);
+		min = low;
+		max = high;
+		1
+	    }
+	    Metric::SquaredEuclidean => {
+		for i in 0..points.len() {
+		    if let Some((low, high)) = voronoi_bounds(points, i) {
+			for axis in 0..3 {
+			    min[axis] = min[axis].min(low[axis]);
+			    max[axis] = max[axis].max(high[axis]);
+			}
+		    }
+		}
+		1
+	    }
+	};
+	for axis in 0..dims(points) {
+	    min[axis] -= margin;
+	    max[axis] += margin;
+	}
+	(points[0].with_coords(min), points[0].with_coords(max))
+    }
+}
+
+// Whether `points[i]` lies on the boundary of the convex hull of the points,
+// which is where squared euclidean regions are unbounded. It does if a line,
+// or in 3D a plane, through it has no point on one side. Such a plane can
+// always be turned around it until it passes through two more points too,
+// unless all of them lie on a line.
+fn on_hull(points: &[Point], i: usize) -> bool {
+    let p = points[i].coords();
+    let offsets = points.iter()
+			.map(|q| sub(q.coords(), p))
+			.filter(|&offset| offset != [0, 0, 0])
+			.collect::<Vec<_>>();
+    // In 2D the line through p and another point is the plane that also
+    // runs along z.
+    let mut spans = offsets.clone();
+    if dims(points) == 2 {
+	spans = vec![[0, 0, 1]];
+    }
+    let mut normals = offsets.iter().flat_map(|&a| {
+	spans.iter().map(move |&b| cross(a, b))
+    }).filter(|&normal| normal != [0, 0, 0]).peekable();
+    if normals.peek().is_none() {
+	return true;
+    }
+    normals.any(|normal| {
+	[1, -1].iter().any(|sign| {
+	    offsets.iter().all(|&offset| sign * dot(normal, offset) <= 0)
+	})
+    })
+}
+
+// Whether the Chebyshev region of `points[i]` is unbounded. Far enough out
+// towards a corner, say up and to the right, every distance grows at the same
+// rate, and a point is as far as it lags behind along whichever axis it lags
+// most. Only points at least as far up and to the right as `points[i]` never
+// lag more than it does, so its region carries on towards any corner that
+// none of the other points lie in.
+fn chebyshev_unbounded(points: &[Point], i: usize) -> bool {
+    let p = points[i].coords();
+    (0..1 << dims(points)).any(|corner: usize| {
+	points.iter().enumerate().all(|(j, q)| {
+	    let q = q.coords();
+	    j == i || (0..dims(points)).any(|axis| {
+		if corner >> axis & 1 == 1 { q[axis] < p[axis] } else { q[axis] > p[axis] }
+	    })
+	})
+    })
+}
+
+// Returns the corners of the box that holds every finite Chebyshev region.
+// Stepping diagonally from a cell towards its point brings it one closer to
+// that point and at most one closer to any other, so regions are star shaped.
+// That means the cells of a finite region outside the bounding box are all
+// reached by following the region out from the surface of the box.
+fn chebyshev_bounds(points: &[Point]) -> ([i64; 3], [i64; 3]) {
+    let (min, max) = bounding_box(points);
+    let finite = (0..points.len()).map(|i| !chebyshev_unbounded(points, i)).collect::<Vec<_>>();
+    let owner = |coords: [i64; 3]| match nearest(points, Metric::Chebyshev, &points[0].with_coords(coords)) {
+	Owner::Point(i) if finite[i] => Some(i),
+	_ => None,
+    };
+    let mut surface = Vec::new();
+    for axis in 0..dims(points) {
+	for &side in &[min[axis], max[axis]] {
+	    let (mut low, mut high) = (min, max);
+	    low[axis] = side;
+	    high[axis] = side;
+	    for z in low[2]..=high[2] {
+		for y in low[1]..=high[1] {
+		    for x in low[0]..=high[0] {
+			surface.push([x, y, z]);
+		    }
+		}
+	    }
+	}
+    }
+    let mut queue = surface.into_par_iter()
+			   .filter_map(|cell| owner(cell).map(|i| (cell, i)))
+			   .collect::<Vec<_>>();
+
+    let (mut low, mut high) = (min, max);
+    let steps = Metric::Chebyshev.steps(dims(points)).unwrap();
+    let mut seen = HashSet::new();
+    while let Some((cell, i)) = queue.pop() {
+	for step in &steps {
+	    let next = [cell[0] + step[0], cell[1] + step[1], cell[2] + step[2]];
+	    let inside = (0..3).all(|axis| min[axis] <= next[axis] && next[axis] <= max[axis]);
+	    if inside || seen.contains(&next) || owner(next) != Some(i) {
+		continue;
+	    }
+	    seen.insert(next);
+	    for axis in 0..3 {
+		low[axis] = low[axis].min(next[axis]);
+		high[axis] = high[axis].max(next[axis]);
+	    }
+	    queue.push((next, i));
+	}
     }
+    (low, high)
 }
 
-// Returns None if tie.
-fn find_best(current: &Point, points: &Vec<Point>) -> Option<Point> {
-    let mut points = points.into_iter().map(|point| {
-	PointAndDist(current.dist(point), point.clone())
+// Returns the corners of the squared euclidean region of `points[i]`, or None
+// if it is unbounded. The region is a convex polygon, or polyhedron in 3D,
+// bounded by the bisectors with every other point, so its corners are the
+// bisector crossings that lie on the right side of all of them.
+fn voronoi_bounds(points: &[Point], i: usize) -> Option<([i64; 3], [i64; 3])> {
+    if on_hull(points, i) {
+	return None;
+    }
+    let p = points[i].coords();
+    // Each bisector as `a · c <= b`.
+    let bisectors = points.iter().map(|q| q.coords()).filter(|&q| q != p).map(|q| {
+	let a = sub(q, p);
+	let b = (dot(q, q) - dot(p, p)) as f64 / 2.0;
+	([a[0] as f64, a[1] as f64, a[2] as f64], b)
     }).collect::<Vec<_>>();
-    points.sort();
-    if points[0].0 == points[1].0 {
-	return None
+    // 2D regions lie in the plane `z = 0`.
+    let flat = ([0.0, 0.0, 1.0], 0.0);
+    let mut min = [f64::MAX; 3];
+    let mut max = [f64::MIN; 3];
+    for (j, first) in bisectors.iter().enumerate() {
+	for (k, second) in bisectors.iter().enumerate().skip(j + 1) {
+	    let thirds = if dims(points) == 2 { &[flat][..] } else { &bisectors[k + 1..] };
+	    for third in thirds {
+		let corner = match solve([first, second, third]) {
+		    Some(corner) => corner,
+		    None => continue,
+		};
+		let inside = bisectors.iter().all(|&(a, b)| {
+		    a[0] * corner[0] + a[1] * corner[1] + a[2] * corner[2] <= b + 1e-6
+		});
+		if inside {
+		    for axis in 0..3 {
+			min[axis] = min[axis].min(corner[axis]);
+			max[axis] = max[axis].max(corner[axis]);
+		    }
+		}
+	    }
+	}
+    }
+    Some(([min[0].floor() as i64, min[1].floor() as i64, min[2].floor() as i64],
+	  [max[0].ceil() as i64, max[1].ceil() as i64, max[2].ceil() as i64]))
+}
+
+// Finds where three planes `a · c = b` cross, if they do in a single point.
+fn solve(planes: [&([f64; 3], f64); 3]) -> Option<[f64; 3]> {
+    let det = |m: [[f64; 3]; 3]| {
+	m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1]) -
+	m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0]) +
+	m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    };
+    let matrix = [planes[0].0, planes[1].0, planes[2].0];
+    let divisor = det(matrix);
+    if divisor == 0.0 {
+	return None;
+    }
+    // Cramer's rule.
+    let mut corner = [0.0; 3];
+    for (axis, coord) in corner.iter_mut().enumerate() {
+	let mut replaced = matrix;
+	for (row, plane) in replaced.iter_mut().zip(planes.iter()) {
+	    row[axis] = plane.1;
+	}
+	*coord = det(replaced) / divisor;
+    }
+    Some(corner)
+}
+
+// Which input point a cell of the map is closest to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Owner {
+    Point(usize),
+    // Two or more points are equally close.
+    Contested,
+}
+
+// Maps hold owners as the index of the point, or this if the cell is
+// contested, to keep large 3D maps small.
+const CONTESTED: u32 = u32::MAX;
+
+impl Owner {
+    fn pack(self) -> u32 {
+	match self {
+	    Owner::Point(i) => i as u32,
+	    Owner::Contested => CONTESTED,
+	}
     }
-    Some(points[0].1.clone())
+
+    fn unpack(cell: u32) -> Owner {
+	match cell {
+	    CONTESTED => Owner::Contested,
+	    i => Owner::Point(i as usize),
+	}
+    }
+}
+
+// Finds the owner of `cell` by measuring its distance to each point.
+fn nearest(points: &[Point], metric: Metric, cell: &Point) -> Owner {
+    let mut best = (i64::MAX, Owner::Contested);
+    for (i, point) in points.iter().enumerate() {
+	let dist = metric.dist(cell, point);
+	if dist < best.0 {
+	    best = (dist, Owner::Point(i));
+	} else if dist == best.0 {
+	    best.1 = Owner::Contested;
+	}
+    }
+    best.1
 }
 
-fn part1(points: &Vec<Point>) -> i32 {
-    // Find boundaries.
-    let minx = points.iter().map(|p| p.x).min().unwrap();
-    let miny = points.iter().map(|p| p.y).min().unwrap();
-    let maxx = points.iter().map(|p| p.x).max().unwrap();
-    let maxy = points.iter().map(|p| p.y).max().unwrap();
+// Map of the area around the points given by `Metric::extent`. 2D maps are a
+// single layer deep.
+struct Grid {
+    metric: Metric,
+    dims: usize,
+    min: Point,
+    size: [usize; 3],
+    cells: Vec<u32>,
+}
 
-    let mut count = HashMap::new();
-    // Find winners for each point in subspace.
-    for x in minx..=maxx {
-	for y in miny..=maxy {
-	    let current = Point { x: x, y: y };
-	    if let Some(best_point) = find_best(&current, points) {
-		let entry = count.entry(best_point).or_insert(0);
-		*entry += 1;
+impl Grid {
+    fn label(points: &[Point], metric: Metric) -> Grid {
+	let (min, max) = metric.extent(points);
+	Grid::label_area(points, metric, min, max)
+    }
+
+    // Labels the cells from `min` to `max`, which have to include every
+    // point. The distance between two cells is the same whether or not the
+    // cells between them are mapped, so the labels don't depend on the area.
+    fn label_area(points: &[Point], metric: Metric, min: Point, max: Point) -> Grid {
+	let (low, high) = (min.coords(), max.coords());
+	let mut size = [0; 3];
+	for axis in 0..3 {
+	    size[axis] = (high[axis] - low[axis] + 1) as usize;
+	}
+	let mut grid = Grid {
+	    metric,
+	    dims: dims(points),
+	    min,
+	    size,
+	    cells: vec![CONTESTED; size[0] * size[1] * size[2]],
+	};
+	match metric.steps(grid.dims) {
+	    Some(steps) => grid.flood(points, &steps),
+	    None => grid.scan(points),
+	}
+	grid
+    }
+
+    // Labels every cell by flooding outwards from all points at once. A cell
+    // reached at the same distance from different owners is contested.
+    fn flood(&mut self, points: &[Point], steps: &[[i64; 3]]) {
+	// Distance of each cell from its owner, or u32::MAX if it hasn't been
+	// reached yet.
+	let mut dist = vec![u32::MAX; self.cells.len()];
+	let mut queue = VecDeque::new();
+	for (i, point) in points.iter().enumerate() {
+	    let index = self.index(point);
+	    if dist[index] != u32::MAX {
+		// Duplicate coordinates tie everywhere, so their region is contested.
+		self.cells[index] = CONTESTED;
+		continue;
 	    }
+	    dist[index] = 0;
+	    self.cells[index] = i as u32;
+	    queue.push_back(index);
 	}
+	while let Some(index) = queue.pop_front() {
+	    let next = dist[index] + 1;
+	    let owner = self.cells[index];
+	    let current = self.point(index).coords();
+	    for step in steps {
+		let neighbour = self.min.with_coords([current[0] + step[0],
+						      current[1] + step[1],
+						      current[2] + step[2]]);
+		if !self.contains(&neighbour) {
+		    continue;
+		}
+		let neighbour = self.index(&neighbour);
+		if dist[neighbour] == u32::MAX {
+		    dist[neighbour] = next;
+		    self.cells[neighbour] = owner;
+		    queue.push_back(neighbour);
+		} else if dist[neighbour] == next && self.cells[neighbour] != owner {
+		    self.cells[neighbour] = CONTESTED;
+		}
+	    }
+	}
+    }
+
+    // Labels every cell by measuring its distance to each point.
+    fn scan(&mut self, points: &[Point]) {
+	let metric = self.metric;
+	let cells = (0..self.cells.len()).into_par_iter().map(|index| {
+	    nearest(points, metric, &self.point(index)).pack()
+	}).collect();
+	self.cells = cells;
     }
-    // Find ones who will be infinite.
-    let mut infinite = HashSet::new();
-    let mut check_infinite = |x, y| {
-	let current = Point { x: x, y: y };
-	if let Some(best_point) = find_best(&current, points) {
-	    infinite.insert(best_point);
+
+    // Offset of `point` from the corner of the map along each axis.
+    fn offset(&self, point: &Point) -> [i64; 3] {
+	sub(point.coords(), self.min.coords())
+    }
+
+    fn contains(&self, point: &Point) -> bool {
+	let offset = self.offset(point);
+	(0..3).all(|axis| offset[axis] >= 0 && offset[axis] < self.size[axis] as i64)
+    }
+
+    fn index(&self, point: &Point) -> usize {
+	let offset = self.offset(point);
+	(offset[2] as usize * self.size[1] + offset[1] as usize) * self.size[0] + offset[0] as usize
+    }
+
+    fn owner(&self, index: usize) -> Owner {
+	Owner::unpack(self.cells[index])
+    }
+
+    fn point(&self, index: usize) -> Point {
+	let min = self.min.coords();
+	self.min.with_coords([min[0] + (index % self.size[0]) as i64,
+			      min[1] + (index / self.size[0] % self.size[1]) as i64,
+			      min[2] + (index / self.size[0] / self.size[1]) as i64])
+    }
+
+    // Number of cells owned by each point.
+    fn areas(&self) -> HashMap<usize, usize> {
+	let mut areas = HashMap::new();
+	for index in 0..self.cells.len() {
+	    if let Owner::Point(i) = self.owner(index) {
+		*areas.entry(i).or_insert(0) += 1;
+	    }
+	}
+	areas
+    }
+
+    // Points whose regions keep growing forever.
+    fn infinite(&self, points: &[Point]) -> HashSet<usize> {
+	match self.metric {
+	    Metric::Chebyshev => return (0..points.len()).filter(|&i| chebyshev_unbounded(points, i)).collect(),
+	    Metric::SquaredEuclidean => return (0..points.len()).filter(|&i| on_hull(points, i)).collect(),
+	    Metric::Manhattan => {}
 	}
+	// The map reaches far enough that any region on its border carries
+	// on past it.
+	(0..self.cells.len()).filter(|&index| {
+	    let offset = self.offset(&self.point(index));
+	    (0..self.dims).any(|axis| offset[axis] == 0 || offset[axis] == self.size[axis] as i64 - 1)
+	}).filter_map(|index| match self.owner(index) {
+	    Owner::Point(i) => Some(i),
+	    Owner::Contested => None,
+	}).collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Area {
+    // Number of cells, which is the volume for 3D points.
+    Finite(usize),
+    Infinite,
+}
+
+// Everything known about the region around one of the input points. The
+// bounds and neighbours of infinite regions only cover the mapped area.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Region {
+    point: Point,
+    area: Area,
+    // Corners of the box around the region's cells, if it has any.
+    bounds: Option<(Point, Point)>,
+    // Regions that share an edge with this one, or that are only kept apart
+    // by a line of contested cells.
+    neighbours: BTreeSet<usize>,
+}
+
+fn regions(points: &[Point], metric: Metric) -> Vec<Region> {
+    let grid = Grid::label(points, metric);
+    let areas = grid.areas();
+    let infinite = grid.infinite(points);
+    let mut regions = points.iter().enumerate().map(|(i, point)| Region {
+	point: point.clone(),
+	area: if infinite.contains(&i) {
+	    Area::Infinite
+	} else {
+	    Area::Finite(areas.get(&i).cloned().unwrap_or(0))
+	},
+	bounds: None,
+	neighbours: BTreeSet::new(),
+    }).collect::<Vec<_>>();
+
+    let steps = Metric::Manhattan.steps(grid.dims).unwrap();
+    for index in 0..grid.cells.len() {
+	let current = grid.point(index);
+	let coords = current.coords();
+	let adjacent = steps.iter().filter_map(|step| {
+	    let neighbour = current.with_coords([coords[0] + step[0],
+						 coords[1] + step[1],
+						 coords[2] + step[2]]);
+	    if !grid.contains(&neighbour) {
+		return None;
+	    }
+	    match grid.owner(grid.index(&neighbour)) {
+		Owner::Point(j) => Some(j),
+		Owner::Contested => None,
+	    }
+	}).collect::<BTreeSet<_>>();
+	match grid.owner(index) {
+	    Owner::Point(i) => {
+		let region = &mut regions[i];
+		region.bounds = Some(match region.bounds.take() {
+		    Some((min, max)) => {
+			let (mut low, mut high) = (min.coords(), max.coords());
+			for axis in 0..3 {
+			    low[axis] = low[axis].min(coords[axis]);
+			    high[axis] = high[axis].max(coords[axis]);
+			}
+			(current.with_coords(low), current.with_coords(high))
+		    }
+		    None => (current.clone(), current),
+		});
+		region.neighbours.extend(adjacent.into_iter().filter(|&j| j != i));
+	    }
+	    Owner::Contested => {
+		for &i in &adjacent {
+		    regions[i].neighbours.extend(adjacent.iter().filter(|&&j| j != i));
+		}
+	    }
+	}
+    }
+    regions
+}
+
+// Returns the area, or for 3D points the volume, of the largest finite
+// region, or 0 if all are infinite.
+fn part1(points: &[Point], metric: Metric) -> usize {
+    regions(points, metric).into_iter().filter_map(|region| match region.area {
+	Area::Finite(area) => Some(area),
+	Area::Infinite => None,
+    }).max().unwrap_or(0)
+}
+
+// Finds where the convex function `f` is smallest, given that it has a
+// minimum within `lo..=hi`.
+fn convex_min<F: Fn(i64) -> i64>(f: F, mut lo: i64, mut hi: i64) -> i64 {
+    while lo < hi {
+	let mid = lo + (hi - lo) / 2;
+	if f(mid) <= f(mid + 1) {
+	    hi = mid;
+	} else {
+	    lo = mid + 1;
+	}
+    }
+    lo
+}
+
+// Finds the run of integers on which the convex function `f` is below `less`,
+// given that `f` has a minimum within `lo..=hi`.
+fn convex_below<F: Fn(i64) -> i64>(f: F, lo: i64, hi: i64, less: i64) -> Option<(i64, i64)> {
+    let lo = convex_min(&f, lo, hi);
+    if f(lo) >= less {
+	return None;
+    }
+    // Walks away from the minimum, doubling the step until `f` is no longer
+    // below `less`, and then narrows down on where that happened.
+    let edge = |dir: i64| {
+	let mut outside = 1;
+	while f(lo + dir * outside) < less {
+	    outside *= 2;
+	}
+	let mut inside = outside / 2;
+	while outside - inside > 1 {
+	    let mid = inside + (outside - inside) / 2;
+	    if f(lo + dir * mid) < less {
+		inside = mid;
+	    } else {
+		outside = mid;
+	    }
+	}
+	lo + dir * inside
     };
-    for x in (minx-1)..=(maxx+1) {
-	check_infinite(x as i32, miny - 1);
-	check_infinite(x as i32, maxy + 1);
-    }
-    for y in (miny-1)..=(maxy+1) {
-	check_infinite(minx - 1, y as i32);
-	check_infinite(maxx + 1, y as i32);
-    }
-    // Remove infinite regions and find max.
-    *count.iter()
-	  .filter(|(point, _)| !infinite.contains(point))
-	  .map(|(_, count)| count)
-	  .max()
-	  .unwrap()
-}
-
-fn part2(points: &Vec<Point>, less: i32) -> usize {
-    // Find boundaries.
-    let minx = points.iter().map(|p| p.x).min().unwrap();
-    let miny = points.iter().map(|p| p.y).min().unwrap();
-    let maxx = points.iter().map(|p| p.x).max().unwrap();
-    let maxy = points.iter().map(|p| p.y).max().unwrap();
-
-    // Check each point in search space.
-    let xs = (minx..=maxx).collect::<Vec<_>>();
-    xs.par_iter().map(|&x| {
-	(miny..=maxy).filter(|&y| {
-	    let current = Point { x: x, y: y };
-	    points.iter().map(|point| {
-		current.dist(point)
-	    }).sum::<i32>() < less
-	}).count()
+    Some((edge(-1), edge(1)))
+}
+
+// Summed length of the offsets along `axis` from every point to `at`.
+fn axis_sum(points: &[Point], metric: Metric, axis: usize, at: i64) -> i64 {
+    points.iter().map(|p| {
+	let mut offset = [0; 3];
+	offset[axis] = at - p.coords()[axis];
+	metric.len(offset)
     }).sum()
 }
 
+// Returns the range of coordinates along each axis that the cells whose summed
+// distance to all points is below `less` lie in, or None if there are no such
+// cells. Every metric is at least as long as the offset along any one axis,
+// and for separable metrics the best choice on the other axes can be added to
+// that, which makes the ranges exact.
+fn safe_ranges(points: &[Point], metric: Metric, less: i64) -> Option<Vec<(i64, i64)>> {
+    let (min, max) = bounding_box(points);
+    let smallest = (0..dims(points)).map(|axis| {
+	if !metric.is_separable() {
+	    return 0;
+	}
+	axis_sum(points, metric, axis, convex_min(|at| axis_sum(points, metric, axis, at),
+						  min[axis], max[axis]))
+    }).collect::<Vec<_>>();
+    (0..dims(points)).map(|axis| {
+	let others = smallest.iter().sum::<i64>() - smallest[axis];
+	convex_below(|at| axis_sum(points, metric, axis, at), min[axis], max[axis], less - others)
+    }).collect()
+}
+
+// Counts the cells whose summed distance is below `less` for a separable
+// metric. The sum then splits into a sum per axis, so it's enough to tabulate
+// those for every coordinate that might qualify and count the combinations of
+// entries that add up to less than `less`.
+fn count_separable(points: &[Point], metric: Metric, less: i64) -> usize {
+    let ranges = match safe_ranges(points, metric, less) {
+	Some(ranges) => ranges,
+	None => return 0,
+    };
+    let tables = ranges.iter().enumerate().map(|(axis, &(lo, hi))| {
+	let mut sums = (lo..=hi).map(|at| axis_sum(points, metric, axis, at)).collect::<Vec<_>>();
+	sums.sort();
+	sums
+    }).collect::<Vec<_>>();
+    count_combinations(&tables, less)
+}
+
+// Counts the ways to pick one entry from each of the sorted `tables` so that
+// they add up to less than `less`.
+fn count_combinations(tables: &[Vec<i64>], less: i64) -> usize {
+    let (last, rest) = tables.split_last().unwrap();
+    if rest.len() == 1 {
+	// The larger the entry from one table, the fewer from the other fit
+	// with it.
+	let mut fitting = rest[0].len();
+	return last.iter().map(|entry| {
+	    while fitting > 0 && entry + rest[0][fitting - 1] >= less {
+		fitting -= 1;
+	    }
+	    fitting
+	}).sum();
+    }
+    last.par_iter()
+	.filter(|&&entry| entry < less)
+	.map(|entry| count_combinations(rest, less - entry))
+	.sum()
+}
+
+// Counts the cells whose summed distance to all points is below `less`. These
+// can lie well outside the bounding box, so rather than searching a fixed area
+// each column is searched for exactly the cells that qualify.
+fn part2(points: &[Point], metric: Metric, less: i64) -> usize {
+    if metric.is_separable() {
+	return count_separable(points, metric, less);
+    }
+    let (min, max) = bounding_box(points);
+    let last = dims(points) - 1;
+    let ranges = match safe_ranges(points, metric, less) {
+	Some(ranges) => ranges,
+	None => return 0,
+    };
+
+    let mut columns = vec![[0; 3]];
+    for (axis, &(lo, hi)) in ranges.iter().enumerate().take(last) {
+	columns = columns.into_iter().flat_map(|column: [i64; 3]| {
+	    (lo..=hi).map(move |at| {
+		let mut column = column;
+		column[axis] = at;
+		column
+	    })
+	}).collect();
+    }
+    // The summed distance is convex along each column as well.
+    columns.par_iter().map(|&column| {
+	let total = |at| {
+	    let mut cell = column;
+	    cell[last] = at;
+	    points.iter().map(|p| metric.len(sub(cell, p.coords()))).sum()
+	};
+	convex_below(total, min[last], max[last], less).map_or(0, |(lo, hi)| (hi - lo + 1) as usize)
+    }).sum()
+}
+
+// Cells of the bounding box grown by one cell on every side, row by row,
+// along with their owner on the map. Given `less`, the area is grown further
+// to hold every cell whose summed distance is below it, which can reach well
+// past the box. Only 2D maps can be drawn.
+fn picture(points: &[Point], metric: Metric, less: Option<i64>) -> Vec<Vec<(Point, Owner)>> {
+    assert_eq!(dims(points), 2, "only 2D maps can be drawn");
+    let (mut min, mut max) = bounding_box(points);
+    for axis in 0..2 {
+	min[axis] -= 1;
+	max[axis] += 1;
+    }
+    if let Some(ranges) = less.and_then(|less| safe_ranges(points, metric, less)) {
+	for (axis, (lo, hi)) in ranges.into_iter().enumerate() {
+	    min[axis] = min[axis].min(lo);
+	    max[axis] = max[axis].max(hi);
+	}
+    }
+    let grid = Grid::label_area(points, metric, points[0].with_coords(min), points[0].with_coords(max));
+    (min[1]..=max[1]).map(|y| {
+	(min[0]..=max[0]).map(|x| {
+	    let current = Point { x: x as i32, y: y as i32, z: None };
+	    let owner = grid.owner(grid.index(&current));
+	    (current, owner)
+	}).collect()
+    }).collect()
+}
+
+// Draws the map the way the puzzle does: each region in its own lower-case
+// letter, the points themselves upper-case and contested cells as `.`.
+// Letters start over after `z`, so this is only useful for small inputs.
+fn render_ascii(points: &[Point], metric: Metric) -> String {
+    let mut map = String::new();
+    for row in picture(points, metric, None) {
+	for (current, owner) in row {
+	    map.push(match owner {
+		Owner::Point(i) => {
+		    let letter = (b'a' + (i % 26) as u8) as char;
+		    if current == points[i] { letter.to_ascii_uppercase() } else { letter }
+		}
+		Owner::Contested => '.',
+	    });
+	}
+	map.push('\n');
+    }
+    map
+}
+
+// Picks well spread out colours by stepping around the colour wheel by the
+// golden angle.
+fn region_colour(i: usize) -> [u8; 3] {
+    let hue = (i as f64 * 0.618_033_988_75).fract() * 6.0;
+    let (value, saturation) = (0.9, 0.6);
+    let chroma = value * saturation;
+    let rising = chroma * (1.0 - (hue % 2.0 - 1.0).abs());
+    let (r, g, b) = match hue as u32 {
+	0 => (chroma, rising, 0.0),
+	1 => (rising, chroma, 0.0),
+	2 => (0.0, chroma, rising),
+	3 => (0.0, rising, chroma),
+	4 => (rising, 0.0, chroma),
+	_ => (chroma, 0.0, rising),
+    };
+    let base = value - chroma;
+    [((r + base) * 255.0) as u8, ((g + base) * 255.0) as u8, ((b + base) * 255.0) as u8]
+}
+
+// Writes the map as a binary PPM image, one pixel per cell and one colour per
+// region. Points are black, contested cells white, and cells whose summed
+// distance is below `less` are darkened. The image covers the same area as
+// `render_ascii`, grown to fit all of the darkened cells.
+fn render_ppm<W: Write>(points: &[Point], metric: Metric, less: i64, mut out: W) -> io::Result<()> {
+    let picture = picture(points, metric, Some(less));
+    writeln!(out, "P6\n{} {}\n255", picture[0].len(), picture.len())?;
+    for row in picture {
+	for (current, owner) in row {
+	    let mut colour = match owner {
+		Owner::Point(i) if current == points[i] => [0, 0, 0],
+		Owner::Point(i) => region_colour(i),
+		Owner::Contested => [255, 255, 255],
+	    };
+	    if points.iter().map(|p| metric.dist(&current, p)).sum::<i64>() < less {
+		for channel in colour.iter_mut() {
+		    *channel /= 2;
+		}
+	    }
+	    out.write_all(&colour)?;
+	}
+    }
+    out.flush()
+}
+
 fn main() {
     let input = include_str!("input.txt").trim();
     let points = parse_input(input);
-    println!("part1: {:?}", part1(&points));
-    println!("part2: {:?}", part2(&points, 10000));
+    let mut args = env::args().skip(1);
+    match args.next().as_deref() {
+	Some("--regions") => {
+	    for (i, region) in regions(&points, Metric::Manhattan).iter().enumerate() {
+		let area = match region.area {
+		    Area::Finite(area) => area.to_string(),
+		    Area::Infinite => "infinite".to_string(),
+		};
+		println!("{} {:?}: area {}, bounds {:?}, neighbours {:?}",
+			 i, region.point.coords(), area, region.bounds, region.neighbours);
+	    }
+	    return;
+	}
+	Some("--ascii") => {
+	    print!("{}", render_ascii(&points, Metric::Manhattan));
+	    return;
+	}
+	Some("--ppm") => {
+	    let path = args.next().expect("usage: --ppm <path>");
+	    let file = BufWriter::new(File::create(path).unwrap());
+	    render_ppm(&points, Metric::Manhattan, 10000, file).unwrap();
+	    return;
+	}
+	_ => {}
+    }
+    println!("part1: {:?}", part1(&points, Metric::Manhattan));
+    println!("part2: {:?}", part2(&points, Metric::Manhattan, 10000));
+    for &metric in &[Metric::Chebyshev, Metric::SquaredEuclidean] {
+	println!("{:?} part1: {:?}", metric, part1(&points, metric));
+    }
 }
 #[cfg(test)]
 mod tests {
@@ -150,7 +912,155 @@ mod tests {
 5, 5
 8, 9";
 	let points = parse_input(input);
-	assert_eq!(part1(&points), 17);
+	assert_eq!(part1(&points, Metric::Manhattan), 17);
+	assert_eq!(part1(&parse_input("3, 4"), Metric::Manhattan), 0);
+	assert_eq!(part1(&parse_input("1, 1\n1, 1\n1, 3\n3, 1\n3, 3\n2, 2"), Metric::Manhattan), 1);
+    }
+
+    // Finds the finite regions by brute force: those are the ones whose area
+    // stops changing as the mapped box grows.
+    fn brute_force(points: &[Point], metric: Metric, margin: i64) -> HashMap<usize, usize> {
+	let areas = |margin: i64| {
+	    let mut areas = HashMap::new();
+	    let zs = if dims(points) == 3 { -margin..=margin } else { 0..=0 };
+	    for z in zs {
+		for y in -margin..=margin {
+		    for x in -margin..=margin {
+			let current = points[0].with_coords([x, y, z]);
+			let dists = points.iter().map(|p| metric.dist(&current, p)).collect::<Vec<_>>();
+			let best = *dists.iter().min().unwrap();
+			if dists.iter().filter(|&&d| d == best).count() == 1 {
+			    let i = dists.iter().position(|&d| d == best).unwrap();
+			    *areas.entry(i).or_insert(0) += 1;
+			}
+		    }
+		}
+	    }
+	    areas
+	};
+	let small = areas(margin);
+	let large = areas(2 * margin);
+	small.into_iter().filter(|(i, area)| large[i] == *area).collect()
+    }
+
+    fn finite_areas(points: &[Point], metric: Metric) -> HashMap<usize, usize> {
+	let grid = Grid::label(points, metric);
+	let infinite = grid.infinite(points);
+	grid.areas()
+	    .into_iter()
+	    .filter(|(i, _)| !infinite.contains(i))
+	    .collect()
+    }
+
+    #[test]
+    fn metric_test() {
+	let inputs = ["1, 1\n1, 6\n8, 3\n3, 4\n5, 5\n8, 9",
+		      "0, 0\n10, 0\n0, 10\n10, 10\n5, 5\n4, 6\n2, 9",
+		      "0, 0\n20, 1\n10, 2\n9, 12\n11, 3"];
+	for input in inputs.iter() {
+	    let points = parse_input(input);
+	    for &metric in &[Metric::Manhattan, Metric::Chebyshev, Metric::SquaredEuclidean] {
+		assert_eq!(finite_areas(&points, metric), brute_force(&points, metric, 60),
+			   "{:?} {:?}", input, metric);
+	    }
+	}
+    }
+
+    #[test]
+    fn three_dimensions_test() {
+	let cube = "0, 0, 0\n6, 0, 0\n0, 6, 0\n0, 0, 6\n6, 6, 0\n6, 0, 6\n0, 6, 6\n6, 6, 6\n3, 3, 3";
+	let skewed = "0, 0, 0\n8, 1, 0\n1, 7, 1\n2, 1, 8\n7, 7, 7\n3, 3, 3\n4, 4, 2";
+	for input in [cube, skewed].iter() {
+	    let points = parse_input(input);
+	    for &metric in &[Metric::Manhattan, Metric::Chebyshev, Metric::SquaredEuclidean] {
+		let finite = finite_areas(&points, metric);
+		assert_eq!(finite, brute_force(&points, metric, 14), "{:?} {:?}", input, metric);
+		assert_eq!(part1(&points, metric), finite.values().cloned().max().unwrap_or(0));
+
+		let less = 60;
+		let mut count = 0;
+		for z in -20..=20 {
+		    for y in -20..=20 {
+			for x in -20..=20 {
+			    let current = points[0].with_coords([x, y, z]);
+			    if points.iter().map(|p| metric.dist(&current, p)).sum::<i64>() < less {
+				count += 1;
+			    }
+			}
+		    }
+		}
+		assert_eq!(part2(&points, metric, less), count, "{:?} {:?}", input, metric);
+	    }
+	}
+
+	// Only the centre of the cube has a finite region, the cells closer to
+	// it than to any corner.
+	assert_eq!(part1(&parse_input(cube), Metric::SquaredEuclidean), 129);
+	// In 3D, points that all lie in one plane have no finite regions.
+	let flat = parse_input("0, 0, 0\n4, 0, 0\n0, 4, 0\n4, 4, 0\n2, 2, 0");
+	assert_eq!(part1(&flat, Metric::Manhattan), 0);
+	assert!(str::parse::<Point>("1, 2, 3, 4").is_err());
+    }
+
+    #[test]
+    fn regions_test() {
+	let points = parse_input("1, 1\n1, 6\n8, 3\n3, 4\n5, 5\n8, 9");
+	let regions = regions(&points, Metric::Manhattan);
+	let areas = regions.iter().map(|region| region.area).collect::<Vec<_>>();
+	assert_eq!(areas, vec![Area::Infinite, Area::Infinite, Area::Infinite,
+			       Area::Finite(9), Area::Finite(17), Area::Infinite]);
+	// D, from the puzzle example.
+	assert_eq!(regions[3].bounds, Some((Point { x: 2, y: 2, z: None }, Point { x: 4, y: 5, z: None })));
+	assert_eq!(regions[3].neighbours.iter().cloned().collect::<Vec<_>>(), vec![0, 1, 4]);
+	// E borders every other region.
+	assert_eq!(regions[4].neighbours.iter().cloned().collect::<Vec<_>>(), vec![0, 1, 2, 3, 5]);
+    }
+
+    #[test]
+    fn render_test() {
+	let points = parse_input("1, 1\n1, 6\n8, 3\n3, 4\n5, 5\n8, 9");
+	// As in the puzzle, plus the extra row below it.
+	assert_eq!(render_ascii(&points, Metric::Manhattan), "\
+aaaaa.cccc
+aAaaa.cccc
+aaaddecccc
+aadddeccCc
+..dDdeeccc
+bb.deEeecc
+bBb.eeee..
+bbb.eeefff
+bbb.eeffff
+bbb.ffffFf
+bbb.ffffff
+");
+
+	let mut image = Vec::new();
+	render_ppm(&points, Metric::Manhattan, 32, &mut image).unwrap();
+	let header = b"P6\n10 11\n255\n";
+	assert!(image.starts_with(header));
+	assert_eq!(image.len(), header.len() + 10 * 11 * 3);
+
+	// A safe region much larger than the map still fits in the image.
+	for &(metric, less) in &[(Metric::Manhattan, 100), (Metric::Chebyshev, 100),
+				 (Metric::SquaredEuclidean, 2000)] {
+	    let (mut min, mut max) = ([0, 0], [9, 10]);
+	    for y in -100..=100 {
+		for x in -100..=100 {
+		    let current = Point { x, y, z: None };
+		    if points.iter().map(|p| metric.dist(&current, p)).sum::<i64>() < less {
+			min = [min[0].min(x), min[1].min(y)];
+			max = [max[0].max(x), max[1].max(y)];
+		    }
+		}
+	    }
+	    assert!(min[0] < 0 && max[1] > 10, "{:?}", metric);
+	    let (width, height) = (max[0] - min[0] + 1, max[1] - min[1] + 1);
+	    let mut image = Vec::new();
+	    render_ppm(&points, metric, less, &mut image).unwrap();
+	    let header = format!("P6\n{} {}\n255\n", width, height);
+	    assert!(image.starts_with(header.as_bytes()), "{:?}", metric);
+	    assert_eq!(image.len(), header.len() + (width * height * 3) as usize);
+	}
     }
 
     #[test]
@@ -162,6 +1072,27 @@ mod tests {
 5, 5
 8, 9";
 	let points = parse_input(input);
-	assert_eq!(part2(&points, 32), 16);
+	assert_eq!(part2(&points, Metric::Manhattan, 32), 16);
+
+	// Safe regions reaching well past the bounding box.
+	let origin = parse_input("0, 0");
+	assert_eq!(part2(&origin, Metric::Manhattan, 10), 181);
+	assert_eq!(part2(&origin, Metric::Chebyshev, 10), 361);
+	assert_eq!(part2(&origin, Metric::SquaredEuclidean, 10), 29);
+	assert_eq!(part2(&origin, Metric::Manhattan, 0), 0);
+	assert_eq!(part2(&origin, Metric::Manhattan, 1_000_000), 1_999_998_000_001);
+	for &metric in &[Metric::Manhattan, Metric::Chebyshev, Metric::SquaredEuclidean] {
+	    let less = 400;
+	    let mut count = 0;
+	    for x in -200..=200 {
+		for y in -200..=200 {
+		    let current = Point { x, y, z: None };
+		    if points.iter().map(|p| metric.dist(&current, p)).sum::<i64>() < less {
+			count += 1;
+		    }
+		}
+	    }
+	    assert_eq!(part2(&points, metric, less), count, "{:?}", metric);
+	}
     }
 }
@@ -1,16 +1,25 @@
-#![feature(drain_filter)]
+#[macro_use]
+extern crate failure;
 
+use failure::Error;
 use regex::Regex;
 use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::collections::BinaryHeap;
+use std::env;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, Write};
 
 #[derive(Debug)]
-struct Pair(char, char);
+struct Pair(String, String);
 
-#[derive(Copy, Clone, Eq, PartialEq)]
-struct Step(char);
+#[derive(Clone, Eq, PartialEq)]
+struct Step(String);
 
 impl Ord for Step {
     fn cmp(&self, other: &Step) -> Ordering {
@@ -28,118 +37,754 @@ impl PartialOrd for Step {
 }
 
 impl Step {
-    fn to_secs(&self, offset: u32) -> u32 {
-	(self.0 as u8) as u32 - 64 + offset
+    // The puzzle's rule, which only covers steps named by a single capital
+    // letter: A takes `offset` + 1 seconds, B `offset` + 2 and so on.
+    fn to_secs(&self, offset: u32) -> Option<u32> {
+	match self.0.as_bytes() {
+	    &[letter] if letter.is_ascii_uppercase() => Some(u32::from(letter) - 64 + offset),
+	    _ => None,
+	}
+    }
+}
+
+// What is known about each step: how long it takes, and the skill tags a
+// worker needs to take it. Steps without a duration fall back to
+// `Step::to_secs`, and steps without tags can be taken by anyone.
+#[derive(Debug)]
+struct StepTable {
+    durations: HashMap<String, u32>,
+    tags: HashMap<String, BTreeSet<String>>,
+    offset: u32,
+}
+
+impl StepTable {
+    // Only the puzzle's rule, with `offset` added to every letter.
+    fn letters(offset: u32) -> StepTable {
+	StepTable { durations: HashMap::new(), tags: HashMap::new(), offset }
+    }
+
+    // Parses one step per line: its name, its duration in seconds and then
+    // the skill tags it needs, if any, such as `compile 30 rust`. Steps it
+    // doesn't name follow the puzzle's rule with `offset` and need no skills.
+    fn parse(input: &str, offset: u32) -> Result<StepTable, Error> {
+	let mut table = StepTable::letters(offset);
+	for line in input.lines().filter(|line| !line.trim().is_empty()) {
+	    let fields = line.split_whitespace().collect::<Vec<_>>();
+	    match fields.as_slice() {
+		&[step, secs, ref tags @ ..] => {
+		    table.insert(step, secs.parse()?);
+		    for tag in tags {
+			table.tag(step, tag);
+		    }
+		}
+		_ => return Err(format_err!("invalid step: {:?}", line)),
+	    }
+	}
+	Ok(table)
+    }
+
+    fn insert(&mut self, step: &str, secs: u32) {
+	self.durations.insert(step.to_string(), secs);
+    }
+
+    // Only lets workers with the `tag` skill take `step`.
+    fn tag(&mut self, step: &str, tag: &str) {
+	self.tags.entry(step.to_string()).or_default().insert(tag.to_string());
+    }
+
+    fn get(&self, step: &Step) -> Option<u32> {
+	self.durations.get(&step.0).cloned().or_else(|| step.to_secs(self.offset))
     }
 }
 
 fn parse_input(input: &str) -> Vec<Pair> {
-    let re = Regex::new(r"Step (.) must be finished before step (.) can begin.").unwrap();
+    let re = Regex::new(r"Step (\S+) must be finished before step (\S+) can begin.").unwrap();
     input.lines().map(|line| {
 	let cap = re.captures(line).unwrap();
-	Pair(cap[1].chars().nth(0).unwrap(), cap[2].chars().nth(0).unwrap())
+	Pair(cap[1].to_string(), cap[2].to_string())
     }).collect::<Vec<_>>()
 }
 
-fn part1(pairs: &Vec<Pair>) -> String {
-    let mut dep_graph = HashMap::new();
+// Steps that wait on each other in a loop, so none of them can ever begin.
+// Each cycle lists its steps in lexicographic order.
+#[derive(Debug, PartialEq, Eq)]
+struct CycleError {
+    cycles: Vec<Vec<String>>,
+}
+
+impl fmt::Display for CycleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	let cycles = self.cycles.iter()
+			.map(|cycle| cycle.join(", "))
+			.collect::<Vec<_>>();
+	write!(f, "dependency cycles between steps {}", cycles.join("; "))
+    }
+}
+
+impl error::Error for CycleError {}
+
+// Finds every group of steps that depend on each other, directly or through
+// other steps, using Tarjan's strongly connected components algorithm. A
+// step that has to be finished before itself is a cycle on its own.
+fn find_cycles(pairs: &[Pair]) -> Vec<Vec<String>> {
+    let mut next = BTreeMap::new();
     for pair in pairs {
-	let entry = dep_graph.entry(pair.1).or_insert(HashSet::new());
-	entry.insert(pair.0);
+	next.entry(pair.0.as_str()).or_insert_with(BTreeSet::new).insert(pair.1.as_str());
+	next.entry(pair.1.as_str()).or_insert_with(BTreeSet::new);
     }
 
-    let dep_free = pairs.iter().map(|pair| pair.0)
-	                .chain(pairs.iter().map(|pair| pair.1))
-			.filter(|step| !dep_graph.contains_key(step))
-		    	.collect::<HashSet<char>>();
+    struct Search<'a> {
+	next: &'a BTreeMap<&'a str, BTreeSet<&'a str>>,
+	index: HashMap<&'a str, usize>,
+	lowlink: HashMap<&'a str, usize>,
+	stack: Vec<&'a str>,
+	on_stack: HashSet<&'a str>,
+	cycles: Vec<Vec<String>>,
+    }
 
-    let mut heap = BinaryHeap::new();
+    impl<'a> Search<'a> {
+	// Walks the steps reachable from `root` depth first. The path being
+	// walked is kept on a stack of frames, each holding a step and the
+	// successors it has left to look at, so long chains of steps don't
+	// overflow the call stack.
+	fn visit(&mut self, root: &'a str) {
+	    let next = self.next;
+	    self.enter(root);
+	    let mut frames = vec![(root, next[root].iter())];
+	    while let Some((step, successors)) = frames.last_mut() {
+		let step = *step;
+		match successors.next() {
+		    Some(&after) if !self.index.contains_key(after) => {
+			self.enter(after);
+			frames.push((after, next[after].iter()));
+		    }
+		    Some(&after) => {
+			if self.on_stack.contains(after) {
+			    let low = self.lowlink[step].min(self.index[after]);
+			    self.lowlink.insert(step, low);
+			}
+		    }
+		    None => {
+			frames.pop();
+			if let Some(&(before, _)) = frames.last() {
+			    let low = self.lowlink[before].min(self.lowlink[step]);
+			    self.lowlink.insert(before, low);
+			}
+			self.finish(step);
+		    }
+		}
+	    }
+	}
+
+	fn enter(&mut self, step: &'a str) {
+	    let index = self.index.len();
+	    self.index.insert(step, index);
+	    self.lowlink.insert(step, index);
+	    self.stack.push(step);
+	    self.on_stack.insert(step);
+	}
 
-    for step in dep_free {
-	heap.push(Step(step));
+	// Called once every successor of `step` has been looked at. If nothing
+	// it reaches leads back above it, it heads a component of its own.
+	fn finish(&mut self, step: &'a str) {
+	    if self.lowlink[step] == self.index[step] {
+		let mut component = Vec::new();
+		while let Some(member) = self.stack.pop() {
+		    self.on_stack.remove(member);
+		    component.push(member.to_string());
+		    if member == step {
+			break;
+		    }
+		}
+		if component.len() > 1 || self.next[step].contains(step) {
+		    component.sort();
+		    self.cycles.push(component);
+		}
+	    }
+	}
     }
 
-    let mut accum = String::new();
-    while let Some(s) = heap.pop() {
-	accum.push(s.0);
-	let dep_free = dep_graph.iter_mut().filter_map(|(&step, deps)| {
-	    deps.remove(&s.0);
-	    if deps.is_empty() {
-		Some(step)
-	    } else {
-		None
+    let mut search = Search {
+	next: &next,
+	index: HashMap::new(),
+	lowlink: HashMap::new(),
+	stack: Vec::new(),
+	on_stack: HashSet::new(),
+	cycles: Vec::new(),
+    };
+    for &step in next.keys() {
+	if !search.index.contains_key(step) {
+	    search.visit(step);
+	}
+    }
+    let mut cycles = search.cycles;
+    cycles.sort();
+    cycles
+}
+
+// The steps and the dependencies between them, along with which steps are
+// done. It keeps its shape as steps are done, so it can be edited and stepped
+// through one step at a time.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Graph {
+    // Maps each step to the steps it waits on.
+    deps: BTreeMap<String, BTreeSet<String>>,
+    done: BTreeSet<String>,
+}
+
+impl Graph {
+    fn new() -> Graph {
+	Graph::default()
+    }
+
+    // Builds the graph for the instructions. Fails if some steps could never
+    // begin.
+    fn from_pairs(pairs: &[Pair]) -> Result<Graph, CycleError> {
+	let cycles = find_cycles(pairs);
+	if !cycles.is_empty() {
+	    return Err(CycleError { cycles });
+	}
+	let mut graph = Graph::new();
+	for pair in pairs {
+	    graph.add_dependency(&pair.0, &pair.1);
+	}
+	Ok(graph)
+    }
+
+    fn add_step(&mut self, step: &str) {
+	self.deps.entry(step.to_string()).or_default();
+    }
+
+    // Removes `step` along with every dependency on it. Returns whether it
+    // was there.
+    fn remove_step(&mut self, step: &str) -> bool {
+	for deps in self.deps.values_mut() {
+	    deps.remove(step);
+	}
+	self.done.remove(step);
+	self.deps.remove(step).is_some()
+    }
+
+    // Makes `after` wait on `before`, adding either step if it's new.
+    fn add_dependency(&mut self, before: &str, after: &str) {
+	self.add_step(before);
+	self.deps.entry(after.to_string()).or_default().insert(before.to_string());
+    }
+
+    // Returns whether `after` was waiting on `before`.
+    fn remove_dependency(&mut self, before: &str, after: &str) -> bool {
+	self.deps.get_mut(after).is_some_and(|deps| deps.remove(before))
+    }
+
+    // The dependencies as instructions, such as for `find_cycles`.
+    fn pairs(&self) -> Vec<Pair> {
+	self.deps.iter().flat_map(|(after, deps)| {
+	    deps.iter().map(move |before| Pair(before.clone(), after.clone()))
+	}).collect()
+    }
+
+    // Steps not done yet whose dependencies all are, in lexicographic order.
+    fn available(&self) -> Vec<String> {
+	self.deps.iter()
+		 .filter(|&(step, deps)| !self.done.contains(step) && deps.is_subset(&self.done))
+		 .map(|(step, _)| step.clone())
+		 .collect()
+    }
+
+    // Marks `step` as done. It has to be available.
+    fn complete(&mut self, step: &str) -> Result<(), Error> {
+	let deps = match self.deps.get(step) {
+	    Some(deps) => deps,
+	    None => return Err(format_err!("no step {}", step)),
+	};
+	if self.done.contains(step) {
+	    return Err(format_err!("step {} is already done", step));
+	}
+	let waiting = deps.difference(&self.done).cloned().collect::<Vec<_>>();
+	if !waiting.is_empty() {
+	    return Err(format_err!("step {} waits on {}", step, waiting.join(", ")));
+	}
+	self.done.insert(step.to_string());
+	Ok(())
+    }
+
+    // Whether every step is done.
+    fn is_done(&self) -> bool {
+	self.done.len() == self.deps.len()
+    }
+}
+
+// Returns the order the steps are done in, one step name per entry.
+fn part1(pairs: &[Pair]) -> Result<Vec<String>, Error> {
+    let mut graph = Graph::from_pairs(pairs)?;
+
+    let mut accum = Vec::new();
+    while let Some(s) = graph.available().into_iter().next() {
+	graph.complete(&s)?;
+	accum.push(s);
+    }
+    Ok(accum)
+}
+
+// One step run by one worker, from its `start` second up to, but not
+// including, its `end` second. Workers are numbered from 1, as in the puzzle.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Task {
+    worker: usize,
+    step: String,
+    start: u32,
+    end: u32,
+}
+
+// What every worker did and when, ordered by start time and worker.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Schedule {
+    worker_count: usize,
+    secs: u32,
+    tasks: Vec<Task>,
+}
+
+impl Schedule {
+    // Renders the second by second table from the puzzle, with a row for
+    // each second up to the one where the last step is done. Columns grow to
+    // fit long step names.
+    fn render_table(&self) -> String {
+	let width = self.tasks.iter()
+			.map(|task| task.step.len())
+			.max()
+			.unwrap_or(1)
+			.max("Worker 1".len());
+	let mut done = self.tasks.clone();
+	done.sort_by(|a, b| (a.end, &a.step).cmp(&(b.end, &b.step)));
+
+	let mut table = String::from("Second");
+	for worker in 1..=self.worker_count {
+	    table += &format!("   {:^w$}", format!("Worker {}", worker), w = width);
+	}
+	table += "   Done\n";
+	for second in 0..=self.secs {
+	    let mut row = format!("{:>4}  ", second);
+	    for worker in 1..=self.worker_count {
+		let step = self.tasks.iter()
+			       .find(|task| task.worker == worker && task.start <= second && second < task.end)
+			       .map_or(".", |task| task.step.as_str());
+		row += &format!("   {:^w$}", step, w = width);
+	    }
+	    row += "   ";
+	    for task in done.iter().take_while(|task| task.end <= second) {
+		row += &task.step;
 	    }
-	}).collect::<Vec<char>>();
+	    table += row.trim_end();
+	    table.push('\n');
+	}
+	table
+    }
 
-	for step in dep_free {
-	    dep_graph.remove(&step);
-	    heap.push(Step(step));
+    fn to_csv(&self) -> String {
+	let mut csv = String::from("worker,step,start,end\n");
+	for task in &self.tasks {
+	    let step = if task.step.contains(&[',', '"', '\n'][..]) {
+		format!("\"{}\"", task.step.replace('"', "\"\""))
+	    } else {
+		task.step.clone()
+	    };
+	    csv += &format!("{},{},{},{}\n", task.worker, step, task.start, task.end);
 	}
+	csv
+    }
+
+    fn to_json(&self) -> String {
+	let tasks = self.tasks.iter().map(|task| {
+	    format!("{{\"worker\":{},\"step\":{},\"start\":{},\"end\":{}}}",
+		    task.worker, json_string(&task.step), task.start, task.end)
+	}).collect::<Vec<_>>();
+	format!("{{\"workers\":{},\"secs\":{},\"tasks\":[{}]}}",
+		self.worker_count, self.secs, tasks.join(","))
     }
-    accum
 }
 
-fn part2(pairs: &Vec<Pair>, worker_count: usize, offset: u32) -> u32 {
-    let mut dep_graph = HashMap::new();
-    for pair in pairs {
-	let entry = dep_graph.entry(pair.1).or_insert(HashSet::new());
-	entry.insert(pair.0);
+// Quotes `s` as a JSON string.
+fn json_string(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+	match c {
+	    '"' => quoted += "\\\"",
+	    '\\' => quoted += "\\\\",
+	    '\n' => quoted += "\\n",
+	    c if (c as u32) < 0x20 => quoted += &format!("\\u{:04x}", c as u32),
+	    c => quoted.push(c),
+	}
+    }
+    quoted.push('"');
+    quoted
+}
+
+// Fails unless every step has a duration.
+fn check_durations(pairs: &[Pair], table: &StepTable) -> Result<(), Error> {
+    let unknown = pairs.iter()
+		       .flat_map(|pair| vec![&pair.0, &pair.1])
+		       .filter(|&step| table.get(&Step(step.clone())).is_none())
+		       .cloned()
+		       .collect::<BTreeSet<_>>();
+    if !unknown.is_empty() {
+	let unknown = unknown.into_iter().collect::<Vec<_>>();
+	return Err(format_err!("no duration for steps {}", unknown.join(", ")));
+    }
+    Ok(())
+}
+
+// How a free worker picks among the steps that are ready to begin. Ties go to
+// the lexicographically first step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Policy {
+    // The puzzle's rule.
+    Alphabetical,
+    LongestDuration,
+    // Steps that more steps directly depend on go first.
+    MostDependents,
+    // Steps heading the longest chain of work still to do go first.
+    LongestRemainingPath,
+}
+
+impl Policy {
+    const ALL: [Policy; 4] = [Policy::Alphabetical,
+			      Policy::LongestDuration,
+			      Policy::MostDependents,
+			      Policy::LongestRemainingPath];
+
+    // Ranks each step, with higher ranks going first.
+    fn priorities(self, pairs: &[Pair], table: &StepTable) -> Result<HashMap<String, u32>, Error> {
+	let path = critical_path(pairs, table)?;
+	Ok(path.timings.iter().map(|(step, timing)| {
+	    let priority = match self {
+		Policy::Alphabetical => 0,
+		Policy::LongestDuration => timing.duration,
+		Policy::MostDependents => pairs.iter().filter(|pair| &pair.0 == step).count() as u32,
+		Policy::LongestRemainingPath => path.secs - timing.latest_start,
+	    };
+	    (step.clone(), priority)
+	}).collect())
+    }
+}
+
+// One worker of the simulation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Worker {
+    // How fast it works in percent of the puzzle's elves. Steps take their
+    // duration divided by that, rounded up to whole seconds.
+    speed: u32,
+    // The skill tags it has, or None if it can take any step. Steps without
+    // tags can be taken by anyone.
+    skills: Option<BTreeSet<String>>,
+}
+
+impl Worker {
+    // The puzzle's identical elves.
+    fn elves(count: usize) -> Vec<Worker> {
+	vec![Worker { speed: 100, skills: None }; count]
+    }
+
+    // Parses one worker per line: its speed in percent followed by its skill
+    // tags, such as `150 rust linux`. Workers without tags can take any step.
+    fn parse_all(input: &str) -> Result<Vec<Worker>, Error> {
+	input.lines().filter(|line| !line.trim().is_empty()).map(|line| {
+	    let mut fields = line.split_whitespace();
+	    let speed = fields.next().unwrap().parse::<u32>()?;
+	    if speed == 0 {
+		return Err(format_err!("invalid speed: {:?}", line));
+	    }
+	    let skills = fields.map(String::from).collect::<BTreeSet<_>>();
+	    Ok(Worker { speed, skills: if skills.is_empty() { None } else { Some(skills) } })
+	}).collect()
     }
 
-    let dep_free = pairs.iter().map(|pair| pair.0)
-	                .chain(pairs.iter().map(|pair| pair.1))
-			.filter(|step| !dep_graph.contains_key(step))
-		    	.collect::<HashSet<char>>();
+    fn can_take(&self, step: &str, table: &StepTable) -> bool {
+	match (&self.skills, table.tags.get(step)) {
+	    (Some(skills), Some(tags)) => tags.is_subset(skills),
+	    _ => true,
+	}
+    }
 
-    let mut heap = BinaryHeap::new();
+    fn secs(&self, duration: u32) -> u32 {
+	(duration * 100).div_ceil(self.speed)
+    }
+}
 
-    for step in dep_free {
-	heap.push(Step(step));
+fn part2(pairs: &[Pair],
+	 table: &StepTable,
+	 workers: &[Worker],
+	 policy: Policy) -> Result<Schedule, Error> {
+    let mut graph = Graph::from_pairs(pairs)?;
+    let priorities = policy.priorities(pairs, table)?;
+    let untaken = priorities.keys()
+			    .filter(|step| !workers.iter().any(|worker| worker.can_take(step, table)))
+			    .cloned()
+			    .collect::<BTreeSet<_>>();
+    if !untaken.is_empty() {
+	let untaken = untaken.into_iter().collect::<Vec<_>>();
+	return Err(format_err!("no worker can take steps {}", untaken.join(", ")));
     }
 
     let mut cycles = 0;
-    let mut workers = Vec::new();
+    let mut tasks = Vec::new();
+    let mut started = HashSet::new();
+    let mut busy: Vec<Option<Task>> = vec![None; workers.len()];
     loop {
-	while workers.len() < worker_count {
-	    if let Some(s) = heap.pop() {
-		workers.push((s.to_secs(offset), s.0));
-	    } else {
+	let mut ready = graph.available()
+			     .into_iter()
+			     .filter(|s| !started.contains(s))
+			     .map(|s| (priorities[&s], Step(s)))
+			     .collect::<BinaryHeap<_>>();
+	// Free workers pick in order, each taking the first ready step it is
+	// able to.
+	for (index, slot) in busy.iter_mut().enumerate().filter(|(_, slot)| slot.is_none()) {
+	    let worker = &workers[index];
+	    let mut skipped = Vec::new();
+	    while let Some((priority, s)) = ready.pop() {
+		if !worker.can_take(&s.0, table) {
+		    skipped.push((priority, s));
+		    continue;
+		}
+		started.insert(s.0.clone());
+		*slot = Some(Task {
+		    worker: index + 1,
+		    start: cycles,
+		    end: cycles + worker.secs(table.get(&s).unwrap()),
+		    step: s.0,
+		});
 		break;
 	    }
+	    ready.extend(skipped);
 	}
-	if workers.is_empty() {
-	    break;
-	}
-	let ticks = workers.iter().min().unwrap().0;
-	cycles += ticks;
-	for (_, s) in workers.drain_filter(|(c, _)| {
-	    *c -= ticks;
-	    *c == 0
-	}) {
-	    let dep_free = dep_graph.iter_mut().filter_map(|(&step, deps)| {
-		deps.remove(&s);
-		if deps.is_empty() {
-		    Some(step)
+	cycles = match busy.iter().flatten().map(|task| task.end).min() {
+	    Some(end) => end,
+	    None => break,
+	};
+	for slot in busy.iter_mut() {
+	    if slot.as_ref().is_some_and(|task| task.end == cycles) {
+		let task = slot.take().unwrap();
+		graph.complete(&task.step)?;
+		tasks.push(task);
+	    }
+	}
+    }
+    tasks.sort_by_key(|task| (task.start, task.worker));
+    Ok(Schedule { worker_count: workers.len(), secs: cycles, tasks })
+}
+
+// Total time taken under each policy, to compare them on the same input.
+fn makespans(pairs: &[Pair], table: &StepTable, workers: &[Worker]) -> Result<Vec<(Policy, u32)>, Error> {
+    Policy::ALL.iter().map(|&policy| {
+	Ok((policy, part2(pairs, table, workers, policy)?.secs))
+    }).collect()
+}
+
+// When a step can run if every step gets a worker of its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Timing {
+    duration: u32,
+    // Second at which the last of its dependencies is done.
+    earliest_start: u32,
+    // Last second it can start without delaying the whole schedule.
+    latest_start: u32,
+}
+
+impl Timing {
+    // How long the step can be put off without delaying the whole schedule.
+    fn slack(&self) -> u32 {
+	self.latest_start - self.earliest_start
+    }
+}
+
+// The schedule with unlimited workers, which bounds how far adding workers to
+// `part2` can help.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CriticalPath {
+    // Shortest possible completion time.
+    secs: u32,
+    // A chain of steps that each begin as soon as the previous one is done
+    // and have no slack. Where there is more than one, the lexicographically
+    // first.
+    steps: Vec<String>,
+    timings: BTreeMap<String, Timing>,
+}
+
+impl CriticalPath {
+    // Whether `pair` runs between two steps on a critical path, so that the
+    // second begins right as the first is done.
+    fn is_critical(&self, pair: &Pair) -> bool {
+	let (before, after) = (&self.timings[&pair.0], &self.timings[&pair.1]);
+	before.slack() == 0 && after.slack() == 0 &&
+	    before.earliest_start + before.duration == after.earliest_start
+    }
+}
+
+fn critical_path(pairs: &[Pair], table: &StepTable) -> Result<CriticalPath, Error> {
+    let order = part1(pairs)?;
+    check_durations(pairs, table)?;
+
+    let mut timings = BTreeMap::new();
+    for step in &order {
+	let earliest_start = pairs.iter()
+				  .filter(|pair| &pair.1 == step)
+				  .map(|pair| {
+				      let before: &Timing = &timings[&pair.0];
+				      before.earliest_start + before.duration
+				  })
+				  .max()
+				  .unwrap_or(0);
+	let duration = table.get(&Step(step.clone())).unwrap();
+	timings.insert(step.clone(), Timing { duration, earliest_start, latest_start: 0 });
+    }
+    let secs = timings.values().map(|timing| timing.earliest_start + timing.duration).max().unwrap_or(0);
+    for step in order.iter().rev() {
+	let latest_end = pairs.iter()
+			      .filter(|pair| &pair.0 == step)
+			      .map(|pair| timings[&pair.1].latest_start)
+			      .min()
+			      .unwrap_or(secs);
+	let timing = timings.get_mut(step).unwrap();
+	timing.latest_start = latest_end - timing.duration;
+    }
+
+    let mut path = CriticalPath { secs, steps: Vec::new(), timings };
+    let mut next = path.timings.iter()
+		       .find(|(_, timing)| timing.slack() == 0 && timing.earliest_start == 0)
+		       .map(|(step, _)| step.clone());
+    while let Some(step) = next {
+	next = pairs.iter()
+		    .filter(|pair| pair.0 == step && path.is_critical(pair))
+		    .map(|pair| pair.1.clone())
+		    .min();
+	path.steps.push(step);
+    }
+    Ok(path)
+}
+
+// Renders the dependency graph in Graphviz's DOT language. Each step is
+// labelled with its duration and its position in the `part1` order, and the
+// edges along critical paths are drawn thick and red.
+fn to_dot(pairs: &[Pair], table: &StepTable) -> Result<String, Error> {
+    let order = part1(pairs)?;
+    let path = critical_path(pairs, table)?;
+    let escape = |s: &str| s.replace('\\', "\\\\").replace('"', "\\\"");
+
+    let mut dot = String::from("digraph steps {\n");
+    for (position, step) in order.iter().enumerate() {
+	dot += &format!("    \"{0}\" [label=\"{0}\\n{1}s, #{2}\"];\n",
+			escape(step), path.timings[step].duration, position + 1);
+    }
+    for pair in pairs {
+	let style = if path.is_critical(pair) { " [color=red, penwidth=2]" } else { "" };
+	dot += &format!("    \"{}\" -> \"{}\"{};\n", escape(&pair.0), escape(&pair.1), style);
+    }
+    dot += "}\n";
+    Ok(dot)
+}
+
+// Lets a driver edit the graph and do its steps one at a time, reading one
+// command per line from `input`:
+//
+//   add STEP, add BEFORE AFTER       adds a step or a dependency
+//   remove STEP, remove BEFORE AFTER removes a step or a dependency
+//   done STEP                        marks a step as done
+//
+// After each command the steps available next are written to `out`.
+// Dependencies that would close a cycle are refused.
+fn drive<R: BufRead, W: Write>(graph: &mut Graph, input: R, mut out: W) -> io::Result<()> {
+    writeln!(out, "available: {}", graph.available().join(" "))?;
+    for line in input.lines() {
+	let line = line?;
+	let words = line.split_whitespace().collect::<Vec<_>>();
+	let result = match *words.as_slice() {
+	    ["add", step] => {
+		graph.add_step(step);
+		Ok(())
+	    }
+	    ["add", before, after] => {
+		let mut edited = graph.clone();
+		edited.add_dependency(before, after);
+		let cycles = find_cycles(&edited.pairs());
+		if cycles.is_empty() {
+		    *graph = edited;
+		    Ok(())
 		} else {
-		    None
+		    Err(CycleError { cycles }.into())
 		}
-	    }).collect::<Vec<char>>();
-
-	    for step in dep_free {
-		dep_graph.remove(&step);
-		heap.push(Step(step));
 	    }
+	    ["remove", step] if graph.remove_step(step) => Ok(()),
+	    ["remove", before, after] if graph.remove_dependency(before, after) => Ok(()),
+	    ["remove", ..] => Err(format_err!("nothing to remove")),
+	    ["done", step] => graph.complete(step),
+	    [] => continue,
+	    _ => Err(format_err!("unknown command: {}", line)),
+	};
+	if let Err(err) = result {
+	    writeln!(out, "error: {}", err)?;
+	}
+	if graph.is_done() {
+	    writeln!(out, "all steps done")?;
+	} else {
+	    writeln!(out, "available: {}", graph.available().join(" "))?;
 	}
     }
-    cycles
+    Ok(())
 }
 
 fn main() {
-    let input = include_str!("input.txt");
+    // Instructions, a table of steps and a list of workers may be given
+    // instead of the puzzle input, after an optional output format.
+    let mut args = env::args().skip(1).collect::<Vec<_>>();
+    let format = if args.first().is_some_and(|arg| arg.starts_with("--")) {
+	Some(args.remove(0))
+    } else {
+	None
+    };
+    let input = match args.first() {
+	Some(path) => fs::read_to_string(path).unwrap(),
+	None => include_str!("input.txt").to_string(),
+    };
+    let table = match args.get(1) {
+	Some(path) => StepTable::parse(&fs::read_to_string(path).unwrap(), 60).unwrap(),
+	None => StepTable::letters(60),
+    };
+    let workers = match args.get(2) {
+	Some(path) => Worker::parse_all(&fs::read_to_string(path).unwrap()).unwrap(),
+	None => Worker::elves(5),
+    };
     let pairs = parse_input(&input);
 
-    println!("part1: {}", part1(&pairs));
-    println!("part1: {}", part2(&pairs, 5, 60));
+    // Only some formats need the schedule, and the others shouldn't fail on
+    // input it can't be made for.
+    let schedule = || part2(&pairs, &table, &workers, Policy::Alphabetical).unwrap();
+    match format.as_deref() {
+	Some("--table") => print!("{}", schedule().render_table()),
+	Some("--csv") => print!("{}", schedule().to_csv()),
+	Some("--json") => println!("{}", schedule().to_json()),
+	Some("--dot") => print!("{}", to_dot(&pairs, &table).unwrap()),
+	Some("--step") => {
+	    let stdin = io::stdin();
+	    let stdout = io::stdout();
+	    drive(&mut Graph::from_pairs(&pairs).unwrap(), stdin.lock(), stdout.lock()).unwrap();
+	}
+	Some("--critical") => {
+	    let path = critical_path(&pairs, &table).unwrap();
+	    println!("{} seconds: {}", path.secs, path.steps.join(" -> "));
+	    println!("step earliest latest slack");
+	    for (step, timing) in &path.timings {
+		println!("{} {} {} {}", step, timing.earliest_start, timing.latest_start, timing.slack());
+	    }
+	}
+	Some(format) => panic!("unknown format {}", format),
+	None => {
+	    println!("part1: {}", part1(&pairs).unwrap().concat());
+	    println!("part2: {}", schedule().secs);
+	    for (policy, secs) in makespans(&pairs, &table, &workers).unwrap() {
+		println!("  {:?}: {}", policy, secs);
+	    }
+	    println!("unlimited workers: {}", critical_path(&pairs, &table).unwrap().secs);
+	}
+    }
 }
 
 #[cfg(test)]
@@ -156,7 +801,7 @@ Step B must be finished before step E can begin.
 Step D must be finished before step E can begin.
 Step F must be finished before step E can begin.";
 	let pairs = parse_input(input);
-	assert_eq!(part1(&pairs).as_str(), "CABDFE");
+	assert_eq!(part1(&pairs).unwrap().concat(), "CABDFE");
     }
 
     #[test]
@@ -169,6 +814,270 @@ Step B must be finished before step E can begin.
 Step D must be finished before step E can begin.
 Step F must be finished before step E can begin.";
 	let pairs = parse_input(input);
-	assert_eq!(part2(&pairs, 2, 0), 15);
+	let schedule = part2(&pairs, &StepTable::letters(0), &Worker::elves(2), Policy::Alphabetical).unwrap();
+	assert_eq!(schedule.secs, 15);
+	assert_eq!(schedule.render_table(), "\
+Second   Worker 1   Worker 2   Done
+   0        C          .
+   1        C          .
+   2        C          .
+   3        A          F       C
+   4        B          F       CA
+   5        B          F       CA
+   6        D          F       CAB
+   7        D          F       CAB
+   8        D          F       CAB
+   9        D          .       CABF
+  10        E          .       CABFD
+  11        E          .       CABFD
+  12        E          .       CABFD
+  13        E          .       CABFD
+  14        E          .       CABFD
+  15        .          .       CABFDE
+");
+	assert_eq!(schedule.to_csv(), "\
+worker,step,start,end
+1,C,0,3
+1,A,3,4
+2,F,3,9
+1,B,4,6
+1,D,6,10
+1,E,10,15
+");
+	assert!(schedule.to_json().starts_with("{\"workers\":2,\"secs\":15,\"tasks\":[\
+{\"worker\":1,\"step\":\"C\",\"start\":0,\"end\":3},{\"worker\":1,\"step\":\"A\","));
+    }
+
+    #[test]
+    fn named_steps_test() {
+	let input = "Step fetch must be finished before step build can begin.
+Step configure must be finished before step build can begin.
+Step build must be finished before step test can begin.
+Step build must be finished before step package can begin.
+Step test must be finished before step deploy can begin.
+Step package must be finished before step deploy can begin.";
+	let pairs = parse_input(input);
+	assert_eq!(part1(&pairs).unwrap(),
+		   vec!["configure", "fetch", "build", "package", "test", "deploy"]);
+
+	let table = StepTable::parse("fetch 5\nconfigure 2\nbuild 30\n\ntest 20\npackage 3\ndeploy 1\n", 0).unwrap();
+	// configure and fetch run side by side, then build, then test and
+	// package side by side.
+	assert_eq!(part2(&pairs, &table, &Worker::elves(2), Policy::Alphabetical).unwrap().secs, 5 + 30 + 20 + 1);
+	assert_eq!(part2(&pairs, &table, &Worker::elves(1), Policy::Alphabetical).unwrap().secs, 5 + 2 + 30 + 20 + 3 + 1);
+
+	// Single letter steps fall back to the puzzle's rule.
+	let pairs = parse_input("Step A must be finished before step lint can begin.");
+	let schedule = part2(&pairs, &StepTable::parse("lint 4", 10).unwrap(), &Worker::elves(1), Policy::Alphabetical).unwrap();
+	assert_eq!(schedule.secs, 11 + 4);
+	assert_eq!(schedule.to_csv(), "worker,step,start,end\n1,A,0,11\n1,lint,11,15\n");
+	let err = part2(&pairs, &StepTable::letters(10), &Worker::elves(1), Policy::Alphabetical).unwrap_err();
+	assert_eq!(err.to_string(), "no duration for steps lint");
+	assert!(StepTable::parse("build", 0).is_err());
+	assert!(StepTable::parse("build soon", 0).is_err());
+    }
+
+    #[test]
+    fn cycle_test() {
+	let input = "Step C must be finished before step A can begin.
+Step A must be finished before step B can begin.
+Step B must be finished before step C can begin.
+Step B must be finished before step D can begin.
+Step E must be finished before step E can begin.
+Step F must be finished before step G can begin.
+Step G must be finished before step F can begin.";
+	let pairs = parse_input(input);
+	let cycles = vec![vec!["A", "B", "C"], vec!["E"], vec!["F", "G"]];
+	let expected = CycleError {
+	    cycles: cycles.into_iter()
+			  .map(|cycle| cycle.into_iter().map(String::from).collect())
+			  .collect(),
+	};
+	assert_eq!(find_cycles(&pairs), expected.cycles);
+	let err = part1(&pairs).unwrap_err();
+	assert_eq!(err.to_string(), "dependency cycles between steps A, B, C; E; F, G");
+	assert_eq!(err.downcast::<CycleError>().unwrap(), expected);
+	assert!(part2(&pairs, &StepTable::letters(0), &Worker::elves(2), Policy::Alphabetical).is_err());
+	// A long chain of steps, first on its own and then closed into a loop.
+	let len = 100_000;
+	let name = |i: usize| format!("s{:06}", i);
+	let mut pairs = (1..len).map(|i| Pair(name(i - 1), name(i))).collect::<Vec<_>>();
+	assert!(find_cycles(&pairs).is_empty());
+	pairs.push(Pair(name(len - 1), name(0)));
+	assert_eq!(find_cycles(&pairs), vec![(0..len).map(name).collect::<Vec<_>>()]);
+    }
+
+    #[test]
+    fn critical_path_test() {
+	let input = "Step C must be finished before step A can begin.
+Step C must be finished before step F can begin.
+Step A must be finished before step B can begin.
+Step A must be finished before step D can begin.
+Step B must be finished before step E can begin.
+Step D must be finished before step E can begin.
+Step F must be finished before step E can begin.";
+	let pairs = parse_input(input);
+	let path = critical_path(&pairs, &StepTable::letters(0)).unwrap();
+	// C takes 3 seconds and F 6, before E can begin at 9.
+	assert_eq!(path.secs, 14);
+	assert_eq!(path.steps, vec!["C", "F", "E"]);
+	let timings = path.timings.iter()
+			  .map(|(step, timing)| (step.as_str(), timing.earliest_start, timing.latest_start, timing.slack()))
+			  .collect::<Vec<_>>();
+	assert_eq!(timings, vec![("A", 3, 4, 1), ("B", 4, 7, 3), ("C", 0, 0, 0),
+				 ("D", 4, 5, 1), ("E", 9, 9, 0), ("F", 3, 3, 0)]);
+	// No number of workers beats it.
+	assert_eq!(part2(&pairs, &StepTable::letters(0), &Worker::elves(6), Policy::Alphabetical).unwrap().secs, path.secs);
+	assert_eq!(critical_path(&[], &StepTable::letters(0)).unwrap().secs, 0);
+    }
+
+    #[test]
+    fn dot_test() {
+	let input = "Step C must be finished before step A can begin.
+Step C must be finished before step F can begin.
+Step A must be finished before step B can begin.
+Step A must be finished before step D can begin.
+Step B must be finished before step E can begin.
+Step D must be finished before step E can begin.
+Step F must be finished before step E can begin.";
+	let pairs = parse_input(input);
+	assert_eq!(to_dot(&pairs, &StepTable::letters(0)).unwrap(), r#"digraph steps {
+    "C" [label="C\n3s, #1"];
+    "A" [label="A\n1s, #2"];
+    "B" [label="B\n2s, #3"];
+    "D" [label="D\n4s, #4"];
+    "F" [label="F\n6s, #5"];
+    "E" [label="E\n5s, #6"];
+    "C" -> "A";
+    "C" -> "F" [color=red, penwidth=2];
+    "A" -> "B";
+    "A" -> "D";
+    "B" -> "E";
+    "D" -> "E";
+    "F" -> "E" [color=red, penwidth=2];
+}
+"#);
+	let pairs = parse_input("Step \"quoted\" must be finished before step A can begin.");
+	let mut table = StepTable::letters(0);
+	table.insert("\"quoted\"", 2);
+	assert!(to_dot(&pairs, &table).unwrap().contains(r#"    "\"quoted\"" -> "A" [color=red, penwidth=2];"#));
+    }
+
+    #[test]
+    fn policy_test() {
+	let input = "Step a must be finished before step b can begin.
+Step long must be finished before step c can begin.
+Step d must be finished before step c can begin.";
+	let pairs = parse_input(input);
+	let mut table = StepTable::letters(0);
+	for &(step, secs) in &[("a", 1), ("b", 1), ("c", 1), ("d", 2), ("long", 10)] {
+	    table.insert(step, secs);
+	}
+	let priorities = |policy: Policy| {
+	    let priorities = policy.priorities(&pairs, &table).unwrap();
+	    ["a", "d", "long"].iter().map(|&step| priorities[step]).collect::<Vec<_>>()
+	};
+	assert_eq!(priorities(Policy::LongestDuration), vec![1, 2, 10]);
+	assert_eq!(priorities(Policy::MostDependents), vec![1, 1, 1]);
+	assert_eq!(priorities(Policy::LongestRemainingPath), vec![2, 3, 11]);
+
+	// Alphabetically, b goes ahead of long once a is done, so long only
+	// begins at 2. Counting dependents puts long ahead of b, but still only
+	// after a and d.
+	let secs = makespans(&pairs, &table, &Worker::elves(2)).unwrap();
+	assert_eq!(secs, vec![(Policy::Alphabetical, 13),
+			      (Policy::LongestDuration, 11),
+			      (Policy::MostDependents, 12),
+			      (Policy::LongestRemainingPath, 11)]);
+	let schedule = part2(&pairs, &table, &Worker::elves(2), Policy::LongestDuration).unwrap();
+	assert_eq!(schedule.tasks[0], Task { worker: 1, step: "long".to_string(), start: 0, end: 10 });
+    }
+
+    #[test]
+    fn workers_test() {
+	let input = "Step fetch must be finished before step build can begin.
+Step build must be finished before step docs can begin.
+Step build must be finished before step test can begin.";
+	let pairs = parse_input(input);
+	let table = StepTable::parse("fetch 4\nbuild 10 rust\ndocs 6 writing\ntest 5 rust", 0).unwrap();
+	let workers = Worker::parse_all("100 writing\n200 rust\n50").unwrap();
+	assert_eq!(workers[1], Worker { speed: 200, skills: Some(vec!["rust".to_string()].into_iter().collect()) });
+	assert_eq!(workers[2].skills, None);
+
+	// Free workers pick in order: the writer takes fetch, but can't build
+	// and leaves that to the rustacean. The slow generalist never gets a
+	// turn.
+	let schedule = part2(&pairs, &table, &workers, Policy::Alphabetical).unwrap();
+	let tasks = schedule.tasks.iter()
+			    .map(|task| (task.worker, task.step.as_str(), task.start, task.end))
+			    .collect::<Vec<_>>();
+	assert_eq!(tasks, vec![(1, "fetch", 0, 4), (2, "build", 4, 9), (1, "docs", 9, 15), (2, "test", 9, 12)]);
+	assert_eq!(schedule.secs, 15);
+	assert_eq!(part2(&pairs, &table, &workers, Policy::Alphabetical).unwrap(), schedule);
+
+	// Odd speeds round up to whole seconds.
+	assert_eq!(Worker { speed: 300, skills: None }.secs(10), 4);
+	let err = part2(&pairs, &table, &workers[..1], Policy::Alphabetical).unwrap_err();
+	assert_eq!(err.to_string(), "no worker can take steps build, test");
+	assert!(Worker::parse_all("0 rust").is_err());
+	assert!(Worker::parse_all("fast").is_err());
+    }
+
+    #[test]
+    fn graph_test() {
+	let mut graph = Graph::new();
+	graph.add_dependency("C", "A");
+	graph.add_dependency("C", "F");
+	graph.add_dependency("A", "B");
+	graph.add_step("D");
+	assert_eq!(graph.available(), vec!["C", "D"]);
+	assert_eq!(graph.complete("A").unwrap_err().to_string(), "step A waits on C");
+	graph.complete("C").unwrap();
+	assert_eq!(graph.available(), vec!["A", "D", "F"]);
+	assert_eq!(graph.complete("C").unwrap_err().to_string(), "step C is already done");
+	assert_eq!(graph.complete("Z").unwrap_err().to_string(), "no step Z");
+
+	// Steps that are done stay done as the graph changes.
+	graph.add_dependency("C", "E");
+	graph.add_dependency("D", "E");
+	assert!(graph.remove_dependency("C", "F"));
+	assert!(!graph.remove_dependency("C", "F"));
+	assert!(graph.remove_step("A"));
+	assert!(!graph.remove_step("A"));
+	assert_eq!(graph.available(), vec!["B", "D", "F"]);
+	assert_eq!(graph.pairs().iter().map(|pair| format!("{}{}", pair.0, pair.1)).collect::<Vec<_>>(),
+		   vec!["CE", "DE"]);
+	for step in &["B", "D", "E"] {
+	    assert!(!graph.is_done());
+	    graph.complete(step).unwrap();
+	}
+	graph.complete("F").unwrap();
+	assert!(graph.is_done());
+    }
+
+    #[test]
+    fn drive_test() {
+	let pairs = parse_input("Step C must be finished before step A can begin.
+Step C must be finished before step F can begin.");
+	let mut graph = Graph::from_pairs(&pairs).unwrap();
+	let commands = "done A\ndone C\nadd A C\nadd F B\n\nremove Z\ndone A\ndone F\nhurry\ndone B\n";
+	let mut out = Vec::new();
+	drive(&mut graph, commands.as_bytes(), &mut out).unwrap();
+	assert_eq!(String::from_utf8(out).unwrap(), "\
+available: C
+error: step A waits on C
+available: C
+available: A F
+error: dependency cycles between steps A, C
+available: A F
+available: A F
+error: nothing to remove
+available: A F
+available: F
+available: B
+error: unknown command: hurry
+available: B
+all steps done
+");
     }
 }
@@ -1,71 +1,593 @@
-fn parse_input(input: &str) -> Vec<u32> {
-    input.trim().split(" ")
-	 .map(str::parse::<u32>)
-	 .collect::<Result<Vec<u32>, std::num::ParseIntError>>()
-	 .unwrap()
-}
-
-fn part1(input: &[u32]) -> (u32, usize)  {
-    assert!(input.len() >= 2);
-    let child_nodes = input[0];
-    let metadata_entries = input[1] as usize;
-    let (child_checksum, first) = (0..child_nodes).fold((0, 2), |(checksum, first), _i| {
-	let last = input.len() - metadata_entries;
-	let (child_checksum, child_size) = part1(&input[first..last]);
-	(checksum + child_checksum,  first + child_size)
-    });
-    assert!(input.len() >= first + metadata_entries);
-    let checksum: u32 = input.iter().skip(first).take(metadata_entries).sum();
-    (checksum + child_checksum, first + metadata_entries)
-}
-
-fn part2(input: &[u32]) -> (u32, usize)  {
-    assert!(input.len() >= 2);
-    let child_nodes = input[0];
-    let metadata_entries = input[1] as usize;
-    // Return metadata as value if no children.
-    if child_nodes == 0 {
-       let value = input.iter().skip(2).take(metadata_entries).sum();
-       return (value, 2 + metadata_entries);
-    }
-
-    // Otherwwise compute it's children's values.
-    let mut first = 2;
-    let children = std::iter::once(0).chain((0..child_nodes).map(|_| {
-	let last = input.len() - metadata_entries;
-	let (child_value, child_size) = part2(&input[first..last]);
-	first += child_size;
-	child_value
-    })).collect::<Vec<_>>();
-
-    // And then compute it's own values.
-    assert!(input.len() >= first + metadata_entries);
-    let value = input.iter().skip(first).take(metadata_entries).fold(0, |sum, entry| {
-	sum + children.get(*entry as usize).unwrap_or(&0)
-    });
-    (value, first + metadata_entries)
+use std::env;
+use std::error;
+use std::fmt;
+use std::mem;
+use std::ops::RangeInclusive;
+use std::process;
+
+// A node of the license tree. The derived `Debug` recurses once per level of
+// nesting, so tests compare deep trees through `serialize`, or with `assert!`
+// so that a mismatch isn't printed.
+#[derive(Debug)]
+struct Node {
+    children: Vec<Node>,
+    metadata: Vec<u32>,
+}
+
+// Dropping a node would otherwise recurse once per level of nesting, which
+// overflows the stack for deep trees.
+impl Drop for Node {
+    fn drop(&mut self) {
+	let mut nodes = mem::take(&mut self.children);
+	while let Some(mut node) = nodes.pop() {
+	    nodes.append(&mut node.children);
+	}
+    }
+}
+
+// Compares nodes pairwise off a stack, for the same reason as `drop`.
+impl PartialEq for Node {
+    fn eq(&self, other: &Node) -> bool {
+	let mut pairs = vec![(self, other)];
+	while let Some((left, right)) = pairs.pop() {
+	    if left.metadata != right.metadata || left.children.len() != right.children.len() {
+		return false;
+	    }
+	    pairs.extend(left.children.iter().zip(&right.children));
+	}
+	true
+    }
+}
+
+impl Eq for Node {}
+
+// Why a license couldn't be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum LicenseError {
+    InvalidNumber(String),
+    // The numbers ran out partway through the header or the metadata of the
+    // node at `path`, given as the index of each child on the way down from
+    // the root.
+    Truncated {
+	path: Vec<usize>,
+	section: Section,
+	expected: usize,
+	available: usize,
+    },
+    // Numbers were left over after the root node.
+    Trailing(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Section {
+    Header,
+    Metadata,
+}
+
+impl fmt::Display for LicenseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	match self {
+	    LicenseError::InvalidNumber(number) => write!(f, "invalid number {:?}", number),
+	    LicenseError::Truncated { path, section, expected, available } => {
+		let path = path.iter().map(|i| format!("/{}", i)).collect::<String>();
+		let section = match section {
+		    Section::Header => "header",
+		    Section::Metadata => "metadata",
+		};
+		write!(f, "node root{} ran out of data in its {}: expected {} numbers, {} available",
+		       path, section, expected, available)
+	    }
+	    LicenseError::Trailing(count) => write!(f, "{} trailing numbers after the root node", count),
+	}
+    }
+}
+
+impl error::Error for LicenseError {}
+
+fn parse_input(input: &str) -> Result<Vec<u32>, LicenseError> {
+    input.split_whitespace()
+	 .map(|number| number.parse::<u32>().map_err(|_| LicenseError::InvalidNumber(number.to_string())))
+	 .collect()
+}
+
+// Builds the tree in a single pass over the numbers, keeping the nodes whose
+// children are still being read on a stack instead of recursing.
+fn parse_tree(input: &[u32]) -> Result<Node, LicenseError> {
+    // Each open node along with how many children it has left to read and
+    // how many metadata entries follow them.
+    let mut open: Vec<(Node, u32, usize)> = Vec::new();
+    let mut numbers = input.iter().cloned();
+    // Path to the node being read, from how many children each open node
+    // has read so far.
+    let path = |open: &[(Node, u32, usize)]| {
+	open.iter().map(|(node, _, _)| node.children.len()).collect::<Vec<_>>()
+    };
+    loop {
+	if numbers.len() < 2 {
+	    return Err(LicenseError::Truncated {
+		path: path(&open),
+		section: Section::Header,
+		expected: 2,
+		available: numbers.len(),
+	    });
+	}
+	let child_nodes = numbers.next().unwrap();
+	let metadata_entries = numbers.next().unwrap() as usize;
+	let node = Node { children: Vec::new(), metadata: Vec::new() };
+	open.push((node, child_nodes, metadata_entries));
+
+	// Close every node that has read all of its children.
+	while let Some(&(_, 0, metadata_entries)) = open.last() {
+	    if numbers.len() < metadata_entries {
+		return Err(LicenseError::Truncated {
+		    path: path(&open[..open.len() - 1]),
+		    section: Section::Metadata,
+		    expected: metadata_entries,
+		    available: numbers.len(),
+		});
+	    }
+	    let (mut node, _, _) = open.pop().unwrap();
+	    node.metadata = numbers.by_ref().take(metadata_entries).collect();
+	    match open.last_mut() {
+		Some((parent, child_nodes, _)) => {
+		    parent.children.push(node);
+		    *child_nodes -= 1;
+		}
+		None if numbers.len() > 0 => return Err(LicenseError::Trailing(numbers.len())),
+		None => return Ok(node),
+	    }
+	}
+    }
+}
+
+// Sums the metadata of every node.
+fn part1(root: &Node) -> u64 {
+    let mut nodes = vec![root];
+    let mut checksum = 0;
+    while let Some(node) = nodes.pop() {
+	checksum += node.metadata.iter().map(|&m| u64::from(m)).sum::<u64>();
+	nodes.extend(&node.children);
+    }
+    checksum
+}
+
+// A node along with what's worked out about it by `entries`.
+struct Entry<'a> {
+    node: &'a Node,
+    depth: usize,
+    // Positions of its children in the entries.
+    children: Vec<usize>,
+    // Values multiply by up to the number of metadata entries at each level,
+    // so they quickly outgrow the entries themselves.
+    value: u64,
+}
+
+impl<'a> Entry<'a> {
+    // Position in the entries of the child a metadata entry points at,
+    // counting from 1, or None if it's out of range.
+    fn reference(&self, entry: u32) -> Option<usize> {
+	(entry as usize).checked_sub(1).and_then(|i| self.children.get(i)).cloned()
+    }
+}
+
+// Lists the nodes depth first, parents before their children, which is the
+// order the puzzle labels them A, B, C and so on.
+fn entries(root: &Node) -> Vec<Entry<'_>> {
+    let mut entries: Vec<Entry> = Vec::new();
+    // Each node waiting to be listed along with the position of its parent.
+    let mut nodes: Vec<(&Node, Option<usize>)> = vec![(root, None)];
+    while let Some((node, parent)) = nodes.pop() {
+	let index = entries.len();
+	let depth = match parent {
+	    Some(parent) => {
+		entries[parent].children.push(index);
+		entries[parent].depth + 1
+	    }
+	    None => 0,
+	};
+	entries.push(Entry { node, depth, children: Vec::new(), value: 0 });
+	nodes.extend(node.children.iter().rev().map(|child| (child, Some(index))));
+    }
+
+    // Children always come after their parents, so going backwards every
+    // child has its value by the time its parent is reached.
+    for index in (0..entries.len()).rev() {
+	let entry = &entries[index];
+	let value = if entry.children.is_empty() {
+	    entry.node.metadata.iter().map(|&m| u64::from(m)).sum()
+	} else {
+	    entry.node.metadata.iter()
+			       .filter_map(|&metadata| entry.reference(metadata))
+			       .map(|child| entries[child].value)
+			       .sum()
+	};
+	entries[index].value = value;
+    }
+    entries
+}
+
+// Computes the value of the root node. A node without children is worth the
+// sum of its metadata, and any other node the sum of the values of the
+// children its metadata entries point at, counting from 1.
+fn part2(root: &Node) -> u64 {
+    entries(root)[0].value
+}
+
+// Names the node at `index` in the entries the way the puzzle does, going on
+// with AA, AB and so on after Z.
+fn label(mut index: usize) -> String {
+    let mut label = Vec::new();
+    loop {
+	label.push(b'A' + (index % 26) as u8);
+	if index < 26 {
+	    break;
+	}
+	index = index / 26 - 1;
+    }
+    label.reverse();
+    String::from_utf8(label).unwrap()
+}
+
+// Prints each node on its own line, indented by its depth, with its metadata
+// and value. For nodes with children it also shows which child each metadata
+// entry points at.
+fn render_tree(root: &Node) -> String {
+    let entries = entries(root);
+    let mut tree = String::new();
+    for (index, entry) in entries.iter().enumerate() {
+	tree += &format!("{:indent$}{} metadata {:?} value {}",
+			 "", label(index), entry.node.metadata, entry.value, indent = 2 * entry.depth);
+	if !entry.children.is_empty() {
+	    let references = entry.node.metadata.iter().map(|&metadata| match entry.reference(metadata) {
+		Some(child) => format!("{} -> {}", metadata, label(child)),
+		None => format!("{} out of range", metadata),
+	    }).collect::<Vec<_>>();
+	    tree += &format!(" refs: {}", references.join(", "));
+	}
+	tree.push('\n');
+    }
+    tree
+}
+
+// Exports the same as `render_tree` as nested JSON objects. References are
+// only listed for nodes with children, with a null child when out of range.
+fn to_json(root: &Node) -> String {
+    let entries = entries(root);
+    let mut json = String::new();
+    // Depths of the nodes whose children are still being written.
+    let mut open: Vec<usize> = Vec::new();
+    for (index, entry) in entries.iter().enumerate() {
+	let mut first = true;
+	while open.last().is_some_and(|&depth| depth >= entry.depth) {
+	    open.pop();
+	    json += "]}";
+	    first = false;
+	}
+	if !first {
+	    json.push(',');
+	}
+	let metadata = entry.node.metadata.iter().map(|m| m.to_string()).collect::<Vec<_>>();
+	let references = if entry.children.is_empty() {
+	    Vec::new()
+	} else {
+	    entry.node.metadata.iter().map(|&metadata| {
+		let child = entry.reference(metadata).map_or("null".to_string(), |child| format!("\"{}\"", label(child)));
+		format!("{{\"entry\":{},\"child\":{}}}", metadata, child)
+	    }).collect()
+	};
+	json += &format!("{{\"label\":\"{}\",\"metadata\":[{}],\"value\":{},\"references\":[{}],\"children\":[",
+			 label(index), metadata.join(","), entry.value, references.join(","));
+	open.push(entry.depth);
+    }
+    for _ in open {
+	json += "]}";
+    }
+    json
+}
+
+// Writes the tree back out in the flat license format: each node's header,
+// then its children, then its metadata.
+fn serialize(root: &Node) -> Vec<u32> {
+    // Each node is visited twice: on the way in for its header, and once its
+    // children are written for its metadata.
+    let mut nodes = vec![(root, false)];
+    let mut numbers = Vec::new();
+    while let Some((node, visited)) = nodes.pop() {
+	if visited {
+	    numbers.extend(&node.metadata);
+	    continue;
+	}
+	numbers.push(node.children.len() as u32);
+	numbers.push(node.metadata.len() as u32);
+	nodes.push((node, true));
+	nodes.extend(node.children.iter().rev().map(|child| (child, false)));
+    }
+    numbers
+}
+
+// Deterministic xorshift, so the same seed always gives the same tree.
+struct Rng(u64);
+
+impl Rng {
+    const MIX: u64 = 0x2018_0008_dead_beef;
+
+    fn new(seed: u64) -> Rng {
+	// Xorshift gets stuck on a state of 0, which the one seed equal to
+	// `MIX` would lead to.
+	match seed ^ Rng::MIX {
+	    0 => Rng(Rng::MIX),
+	    state => Rng(state),
+	}
+    }
+
+    fn range(&mut self, range: &RangeInclusive<u32>) -> u32 {
+	assert!(range.start() <= range.end(), "empty range {:?}", range);
+	self.0 ^= self.0 << 13;
+	self.0 ^= self.0 >> 7;
+	self.0 ^= self.0 << 17;
+	let len = u64::from(range.end() - range.start()) + 1;
+	range.start() + (self.0 % len) as u32
+    }
+}
+
+// Shape of the trees built by `generate`.
+#[derive(Debug, Clone)]
+struct TreeConfig {
+    // Levels of nodes below the root.
+    max_depth: usize,
+    // Children of each node above the bottom level.
+    children: RangeInclusive<u32>,
+    // Metadata entries of each node, and the value of each entry.
+    metadata: RangeInclusive<u32>,
+    values: RangeInclusive<u32>,
+}
+
+impl TreeConfig {
+    // Reads the depth followed by the ranges of children, metadata entries and
+    // values, each given as `LOW-HIGH` or a single number, such as
+    // `8 0-4 1-3 1-9`. Anything left out keeps the shape `main` generated
+    // before it took arguments.
+    fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<TreeConfig, String> {
+	let mut config = TreeConfig { max_depth: 5, children: 0..=4, metadata: 1..=3, values: 1..=9 };
+	if let Some(depth) = args.next() {
+	    config.max_depth = depth.parse().map_err(|_| format!("invalid depth {:?}", depth))?;
+	}
+	for range in [&mut config.children, &mut config.metadata, &mut config.values] {
+	    if let Some(arg) = args.next() {
+		*range = parse_range(&arg)?;
+	    }
+	}
+	match args.next() {
+	    Some(arg) => Err(format!("unexpected argument {:?}", arg)),
+	    None => Ok(config),
+	}
+    }
+}
+
+fn parse_range(arg: &str) -> Result<RangeInclusive<u32>, String> {
+    let invalid = || format!("invalid range {:?}", arg);
+    let (low, high) = match arg.split_once('-') {
+	Some((low, high)) => (low, high),
+	None => (arg, arg),
+    };
+    let (low, high) = (low.parse::<u32>().map_err(|_| invalid())?, high.parse::<u32>().map_err(|_| invalid())?);
+    if low > high {
+	return Err(format!("empty range {:?}", arg));
+    }
+    Ok(low..=high)
+}
+
+// Builds a random tree, keeping the nodes whose children are still being
+// built on a stack the same way `parse_tree` does.
+fn generate(config: &TreeConfig, rng: &mut Rng) -> Node {
+    let mut open: Vec<(Node, u32)> = Vec::new();
+    loop {
+	let child_nodes = if open.len() < config.max_depth { rng.range(&config.children) } else { 0 };
+	open.push((Node { children: Vec::new(), metadata: Vec::new() }, child_nodes));
+
+	while let Some(&(_, 0)) = open.last() {
+	    let (mut node, _) = open.pop().unwrap();
+	    let metadata_entries = rng.range(&config.metadata);
+	    node.metadata = (0..metadata_entries).map(|_| rng.range(&config.values)).collect();
+	    match open.last_mut() {
+		Some((parent, child_nodes)) => {
+		    parent.children.push(node);
+		    *child_nodes -= 1;
+		}
+		None => return node,
+	    }
+	}
+    }
 }
 
 fn main() {
+    let mut args = env::args().skip(1);
+    let mode = args.next();
+    // Prints a fresh random license instead, for the given seed and shape.
+    if let Some("--generate") = mode.as_deref() {
+	let seed = args.next().map_or(Ok(0), |seed| seed.parse()).unwrap();
+	let config = TreeConfig::parse_args(args).unwrap_or_else(|err| {
+	    eprintln!("{}", err);
+	    process::exit(1);
+	});
+	let numbers = serialize(&generate(&config, &mut Rng::new(seed)));
+	println!("{}", numbers.iter().map(|n| n.to_string()).collect::<Vec<_>>().join(" "));
+	return;
+    }
+
     let input = include_str!("input.txt");
-    let input = parse_input(input);
-    println!("part1: {}", part1(&input).0);
-    println!("part2: {}", part2(&input).0);
+    let tree = parse_input(input).and_then(|input| parse_tree(&input)).unwrap_or_else(|err| {
+	eprintln!("{}", err);
+	process::exit(1);
+    });
+    match mode.as_deref() {
+	Some("--tree") => print!("{}", render_tree(&tree)),
+	Some("--json") => println!("{}", to_json(&tree)),
+	_ => {
+	    println!("part1: {}", part1(&tree));
+	    println!("part2: {}", part2(&tree));
+	}
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn parse_tree_test() {
+	let input = parse_input("2 3 0 3 10 11 12 1 1 0 1 99 2 1 1 2").unwrap();
+	let leaf = |metadata: Vec<u32>| Node { children: vec![], metadata };
+	assert_eq!(parse_tree(&input).unwrap(), Node {
+	    children: vec![
+		leaf(vec![10, 11, 12]),
+		Node { children: vec![leaf(vec![99])], metadata: vec![2] },
+	    ],
+	    metadata: vec![1, 1, 2],
+	});
+    }
+
     #[test]
     fn part1_test() {
-	let input = parse_input("2 3 0 3 10 11 12 1 1 0 1 99 2 1 1 2");
-        assert_eq!(part1(&input), (138, input.len()));
+	let input = parse_input("2 3 0 3 10 11 12 1 1 0 1 99 2 1 1 2").unwrap();
+	assert_eq!(part1(&parse_tree(&input).unwrap()), 138);
     }
 
     #[test]
     fn part2_test() {
-	let input = parse_input("2 3 0 3 10 11 12 1 1 0 1 99 2 1 1 2");
-        assert_eq!(part2(&input), (66, input.len()));
+	let input = parse_input("2 3 0 3 10 11 12 1 1 0 1 99 2 1 1 2").unwrap();
+	assert_eq!(part2(&parse_tree(&input).unwrap()), 66);
+    }
+
+    #[test]
+    fn deep_tree_test() {
+	// A chain of nodes, each with one child and one metadata entry of 1.
+	let depth = 1_000_000;
+	let mut input = vec![1, 1];
+	input.extend(std::iter::repeat_n([1, 1], depth - 2).flatten());
+	input.extend(&[0, 1, 5]);
+	input.extend(std::iter::repeat_n(1, depth - 1));
+	let tree = parse_tree(&input).unwrap();
+	assert_eq!(serialize(&tree), input);
+	assert!(tree == parse_tree(&input).unwrap());
+	assert_eq!(part1(&tree), 5 + depth as u64 - 1);
+	assert_eq!(part2(&tree), 5);
+    }
+
+    #[test]
+    fn error_test() {
+	let parse = |input: &str| parse_tree(&parse_input(input)?);
+	let truncated = |path: Vec<usize>, section, expected, available| {
+	    Err(LicenseError::Truncated { path, section, expected, available })
+	};
+	assert_eq!(parse(""), truncated(vec![], Section::Header, 2, 0));
+	assert_eq!(parse("2 3 0 3 10 11 12 1 1 0 1 99 2 1 1"), truncated(vec![], Section::Metadata, 3, 2));
+	assert_eq!(parse("2 3 0 3 10 11 12 1 1 0 1 99"), truncated(vec![1], Section::Metadata, 1, 0));
+	assert_eq!(parse("2 3 0 3 10 11 12 1 1 0"), truncated(vec![1, 0], Section::Header, 2, 1));
+	assert_eq!(parse("2 3 0 3 10"), truncated(vec![0], Section::Metadata, 3, 1));
+	assert_eq!(parse("0 1 5 7 8"), Err(LicenseError::Trailing(2)));
+	assert_eq!(parse("0 1 x"), Err(LicenseError::InvalidNumber("x".to_string())));
+	assert_eq!(parse("0 1 -1"), Err(LicenseError::InvalidNumber("-1".to_string())));
+
+	assert_eq!(parse("2 3 0 3 10 11 12 1 1 0").unwrap_err().to_string(),
+		   "node root/1/0 ran out of data in its header: expected 2 numbers, 1 available");
+	assert_eq!(parse("0 1 5 7 8").unwrap_err().to_string(), "2 trailing numbers after the root node");
+    }
+
+    // The original recursive solution over the flat numbers, returning the
+    // metadata sum, the value and the length of the node at the start of
+    // `input`.
+    fn reference(input: &[u32]) -> (u64, u64, usize) {
+	let (child_nodes, metadata_entries) = (input[0], input[1] as usize);
+	let mut first = 2;
+	let mut checksum = 0;
+	let mut values = vec![0];
+	for _ in 0..child_nodes {
+	    let (child_checksum, child_value, child_size) = reference(&input[first..]);
+	    checksum += child_checksum;
+	    values.push(child_value);
+	    first += child_size;
+	}
+	let metadata = &input[first..first + metadata_entries];
+	let own = metadata.iter().map(|&m| u64::from(m)).sum::<u64>();
+	let value = if child_nodes == 0 {
+	    own
+	} else {
+	    metadata.iter().map(|&entry| values.get(entry as usize).unwrap_or(&0)).sum()
+	};
+	(checksum + own, value, first + metadata_entries)
+    }
+
+    #[test]
+    fn round_trip_test() {
+	let input = parse_input("2 3 0 3 10 11 12 1 1 0 1 99 2 1 1 2").unwrap();
+	assert_eq!(serialize(&parse_tree(&input).unwrap()), input);
+
+	let configs = [
+	    TreeConfig { max_depth: 4, children: 0..=3, metadata: 1..=3, values: 0..=4 },
+	    TreeConfig { max_depth: 1, children: 50..=100, metadata: 0..=2, values: 1..=200 },
+	    TreeConfig { max_depth: 200, children: 1..=1, metadata: 1..=1, values: 1..=2 },
+	];
+	let mut rng = Rng::new(8);
+	for config in &configs {
+	    for _ in 0..20 {
+		let tree = generate(config, &mut rng);
+		let numbers = serialize(&tree);
+		assert_eq!(serialize(&parse_tree(&numbers).unwrap()), numbers);
+		assert_eq!(reference(&numbers), (part1(&tree), part2(&tree), numbers.len()));
+	    }
+	}
+	// Every node of this chain points at its child four times, so the value
+	// of the root is 4^17, well past what fits in 32 bits.
+	let config = TreeConfig { max_depth: 16, children: 1..=1, metadata: 4..=4, values: 1..=1 };
+	let tree = generate(&config, &mut rng);
+	assert_eq!((part1(&tree), part2(&tree)), (68, 1 << 34));
+	assert_eq!(reference(&serialize(&tree)), (68, 1 << 34, 17 * 6));
+
+	let args = |args: &str| TreeConfig::parse_args(args.split_whitespace().map(String::from));
+	let config = args("16 1 4-4 0-9").unwrap();
+	assert_eq!((config.max_depth, config.children, config.metadata, config.values), (16, 1..=1, 4..=4, 0..=9));
+	assert_eq!(args("").unwrap().children, 0..=4);
+	assert_eq!(args("3 4-1").unwrap_err(), "empty range \"4-1\"");
+	assert!(args("3 1-x").is_err());
+	assert!(args("3 1 1 1 1").is_err());
+
+	// The seed that cancels out the mixing still gives a stream.
+	let mut rng = Rng::new(Rng::MIX);
+	assert_ne!((0..4).map(|_| rng.range(&(0..=u32::MAX))).collect::<Vec<_>>(), vec![0; 4]);
+	// The same seed gives the same tree.
+	assert_eq!(serialize(&generate(&configs[0], &mut Rng::new(1))),
+		   serialize(&generate(&configs[0], &mut Rng::new(1))));
+    }
+
+    #[test]
+    fn large_tree_test() {
+	let config = TreeConfig { max_depth: 6, children: 0..=8, metadata: 1..=5, values: 1..=9 };
+	let tree = generate(&config, &mut Rng::new(2018));
+	let numbers = serialize(&tree);
+	assert!(numbers.len() > 10_000);
+	assert_eq!(serialize(&parse_tree(&numbers).unwrap()), numbers);
+	assert_eq!(reference(&numbers), (part1(&tree), part2(&tree), numbers.len()));
+    }
+
+    #[test]
+    fn render_test() {
+	let input = parse_input("2 3 0 3 10 11 12 1 1 0 1 99 2 1 1 2").unwrap();
+	let tree = parse_tree(&input).unwrap();
+	assert_eq!(render_tree(&tree), "\
+A metadata [1, 1, 2] value 66 refs: 1 -> B, 1 -> B, 2 -> C
+  B metadata [10, 11, 12] value 33
+  C metadata [2] value 0 refs: 2 out of range
+    D metadata [99] value 99
+");
+	assert_eq!(to_json(&tree), concat!(
+	    r#"{"label":"A","metadata":[1,1,2],"value":66,"#,
+	    r#""references":[{"entry":1,"child":"B"},{"entry":1,"child":"B"},{"entry":2,"child":"C"}],"children":["#,
+	    r#"{"label":"B","metadata":[10,11,12],"value":33,"references":[],"children":[]},"#,
+	    r#"{"label":"C","metadata":[2],"value":0,"references":[{"entry":2,"child":null}],"children":["#,
+	    r#"{"label":"D","metadata":[99],"value":99,"references":[],"children":[]}]}]}"#));
+
+	let labels = [0, 1, 25, 26, 27, 26 * 27 - 1, 26 * 27].iter().map(|&i| label(i)).collect::<Vec<_>>();
+	assert_eq!(labels, vec!["A", "B", "Z", "AA", "AB", "ZZ", "AAA"]);
     }
 }